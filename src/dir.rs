@@ -1,5 +1,5 @@
 /// Print the `dotfiles` directory location.
 pub fn print_dir() {
-    println!("{}", "/home/drewl/Desktop/dotfile");
+    println!("/home/drewl/Desktop/dotfile");
     // TODO
 }
@@ -0,0 +1,136 @@
+//! The error type for the whole crate.
+use std::fmt;
+use std::io;
+use std::path::{Path, PathBuf};
+
+#[cfg(test)]
+mod tests;
+
+/// Exit code for invalid input from the user. Matches the exit code `clap`
+/// uses for invalid arguments.
+pub const EXIT_USAGE: i32 = 2;
+/// Exit code for invalid configuration files.
+pub const EXIT_CONFIG: i32 = 3;
+/// Exit code for files that can't be read or written.
+pub const EXIT_IO: i32 = 4;
+/// Exit code for commands that can't be run or fail.
+pub const EXIT_COMMAND: i32 = 5;
+/// Exit code for operations that ran but failed for some packages or package systems.
+pub const EXIT_FAILED: i32 = 6;
+/// Exit code for package manager commands that changed and weren't approved.
+pub const EXIT_UNTRUSTED: i32 = 7;
+
+/// Everything that can go wrong.
+#[derive(Debug)]
+pub enum Error {
+    /// A file or folder could not be read, written or created.
+    Io {
+        /// What was being done, such as `read` or `create`.
+        action: &'static str,
+        path: PathBuf,
+        source: io::Error,
+    },
+    /// A TOML file has invalid syntax or doesn't have the expected structure.
+    Toml { path: Option<PathBuf>, source: toml::de::Error },
+    /// A configuration file is well-formed but invalid, such as a template
+    /// that doesn't render or a regex that doesn't compile.
+    Config { path: Option<PathBuf>, message: String },
+    /// A command could not be run, failed or returned unusable output.
+    Command { message: String, source: Option<io::Error> },
+    /// The user asked for something that doesn't exist or isn't allowed.
+    Usage(String),
+    /// The operation ran, but some packages or package systems failed.
+    Failed(String),
+    /// A package system's commands changed and the user didn't approve them.
+    Untrusted(String),
+}
+impl Error {
+    /// Build an `Error::Io`.
+    pub fn io(action: &'static str, path: impl AsRef<Path>, source: io::Error) -> Self {
+        Error::Io {
+            action,
+            path: path.as_ref().to_path_buf(),
+            source,
+        }
+    }
+
+    /// Build an `Error::Config` that isn't tied to a file yet.
+    pub fn config(message: impl Into<String>) -> Self {
+        Error::Config {
+            path: None,
+            message: message.into(),
+        }
+    }
+
+    /// Build an `Error::Command` that has no underlying error.
+    pub fn command(message: impl Into<String>) -> Self {
+        Error::Command {
+            message: message.into(),
+            source: None,
+        }
+    }
+
+    /// Attach `path` to a configuration error that isn't tied to a file yet.
+    /// Other errors are returned unchanged.
+    //# UNIT TESTED
+    pub fn in_file(self, path: impl AsRef<Path>) -> Self {
+        match self {
+            Error::Toml { path: None, source } => Error::Toml {
+                path: Some(path.as_ref().to_path_buf()),
+                source,
+            },
+            Error::Config { path: None, message } => Error::Config {
+                path: Some(path.as_ref().to_path_buf()),
+                message,
+            },
+            error => error,
+        }
+    }
+
+    /// The process exit code for this error.
+    //# UNIT TESTED
+    pub fn exit_code(&self) -> i32 {
+        match self {
+            Error::Usage(_) => EXIT_USAGE,
+            Error::Toml { .. } | Error::Config { .. } => EXIT_CONFIG,
+            Error::Io { .. } => EXIT_IO,
+            Error::Command { .. } => EXIT_COMMAND,
+            Error::Failed(_) => EXIT_FAILED,
+            Error::Untrusted(_) => EXIT_UNTRUSTED,
+        }
+    }
+}
+impl fmt::Display for Error {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Error::Io { action, path, source } => write!(f, "Failed to {} `{}`: {}", action, path.to_string_lossy(), source),
+            // the TOML error already says where in the file the problem is
+            Error::Toml { path: Some(path), source } => write!(f, "Invalid TOML in `{}`: {}", path.to_string_lossy(), source),
+            Error::Toml { path: None, source } => write!(f, "Invalid TOML: {}", source),
+            Error::Config { path: Some(path), message } => write!(f, "Invalid configuration in `{}`: {}", path.to_string_lossy(), message),
+            Error::Config { path: None, message } => write!(f, "{}", message),
+            Error::Command {
+                message,
+                source: Some(source),
+            } => write!(f, "{}: {}", message, source),
+            Error::Command { message, source: None } | Error::Usage(message) | Error::Failed(message) | Error::Untrusted(message) => {
+                write!(f, "{}", message)
+            }
+        }
+    }
+}
+impl std::error::Error for Error {
+    fn source(&self) -> Option<&(dyn std::error::Error + 'static)> {
+        match self {
+            Error::Io { source, .. } => Some(source),
+            Error::Toml { source, .. } => Some(source),
+            Error::Command { source: Some(source), .. } => Some(source),
+            _ => None,
+        }
+    }
+}
+impl From<toml::de::Error> for Error {
+    fn from(source: toml::de::Error) -> Self {
+        Error::Toml { path: None, source }
+    }
+}
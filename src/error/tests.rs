@@ -0,0 +1,34 @@
+use super::*;
+
+/// Tests `Error::in_file()`.
+#[test]
+fn in_file() {
+    let error = Error::config("bad regex").in_file("package-manager.toml");
+    assert_eq!(error.to_string(), "Invalid configuration in `package-manager.toml`: bad regex");
+    // a path that's already set is kept
+    let error = error.in_file("other.toml");
+    assert_eq!(error.to_string(), "Invalid configuration in `package-manager.toml`: bad regex");
+    // other errors don't have a path to fill in
+    assert_eq!(Error::Usage(String::from("unknown")).in_file("other.toml").to_string(), "unknown");
+}
+
+/// TOML errors keep the line and column of the problem.
+#[test]
+fn toml_location() {
+    let error = Error::from(toml::from_str::<toml::Table>("a = 1\nb = \n").unwrap_err()).in_file("settings.toml");
+    let message = error.to_string();
+    assert!(message.starts_with("Invalid TOML in `settings.toml`"), "{}", message);
+    assert!(message.contains("line 2"), "{}", message);
+    assert!(std::error::Error::source(&error).is_some());
+}
+
+/// Tests `Error::exit_code()`.
+#[test]
+fn exit_code() {
+    assert_eq!(Error::Usage(String::new()).exit_code(), EXIT_USAGE);
+    assert_eq!(Error::config("").exit_code(), EXIT_CONFIG);
+    assert_eq!(Error::io("read", "file", io::Error::from(io::ErrorKind::NotFound)).exit_code(), EXIT_IO);
+    assert_eq!(Error::command("").exit_code(), EXIT_COMMAND);
+    assert_eq!(Error::Failed(String::new()).exit_code(), EXIT_FAILED);
+    assert_eq!(Error::Untrusted(String::new()).exit_code(), EXIT_UNTRUSTED);
+}
@@ -1,12 +1,21 @@
 pub mod dir;
+pub mod error;
 pub mod file;
 pub mod pac;
 
 use std::path::PathBuf;
 use std::sync::LazyLock;
 
-use dirs;
+pub use error::Error;
 
 pub const APP_NAME: &str = "dotfile";
 
 pub static CONFIG_DIR: LazyLock<PathBuf> = LazyLock::new(|| dirs::config_dir().expect("Unable to determine user's config directory").join(APP_NAME));
+/// Local state that isn't synced with the dotfiles. Falls back to the local
+/// data directory on systems without a state directory.
+pub static STATE_DIR: LazyLock<PathBuf> = LazyLock::new(|| {
+    dirs::state_dir()
+        .or_else(dirs::data_local_dir)
+        .expect("Unable to determine user's state directory")
+        .join(APP_NAME)
+});
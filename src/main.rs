@@ -1,11 +1,21 @@
 use clap::{Parser, Subcommand};
 use dotfile::dir;
+use dotfile::pac;
 use dotfile::pac::cli::*;
-use dotfile::pac::{self, *};
+
+/// Exit codes for scripts, shown in `--help`.
+const EXIT_CODES: &str = "Exit codes:
+  0  success
+  2  invalid arguments or input, such as an unknown package system
+  3  invalid configuration file
+  4  a file could not be read or written
+  5  a package manager command could not be run or failed
+  6  some packages or package systems failed
+  7  package manager commands changed and were not approved";
 
 /// Main CLI parser.
 #[derive(Parser)]
-#[command(version, about, long_about = None, propagate_version = true)]
+#[command(version, about, long_about = None, propagate_version = true, after_help = EXIT_CODES)]
 struct Cli {
     #[command(subcommand)]
     subcommand: Command,
@@ -16,6 +26,10 @@ struct Cli {
 enum Command {
     /// Manage system packages
     Pac {
+        /// Fail instead of asking when package manager commands changed since
+        /// they were approved
+        #[arg(long, global = true)]
+        refuse_untrusted: bool,
         #[command(subcommand)]
         subcommand: PacCommand,
     },
@@ -26,10 +40,17 @@ enum Command {
 fn main() {
     let cli = Cli::parse();
 
-    if let Err(error_message) = match &cli.subcommand {
-        Command::Pac { subcommand } => pac::cli::process_command(subcommand),
-        Command::Dir => Ok(dir::print_dir()),
+    if let Err(error) = match &cli.subcommand {
+        Command::Pac {
+            refuse_untrusted,
+            subcommand,
+        } => pac::cli::process_command(subcommand, *refuse_untrusted),
+        Command::Dir => {
+            dir::print_dir();
+            Ok(())
+        }
     } {
-        println!("{}", error_message);
+        eprintln!("error: {}", error);
+        std::process::exit(error.exit_code());
     }
 }
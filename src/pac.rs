@@ -1,14 +1,40 @@
+pub mod all;
+pub mod backend;
+pub mod check;
 pub mod cli;
+pub mod exclusion;
+pub mod list_parser;
+pub mod package_list;
 pub mod package_manager;
 pub mod package_system;
 pub mod parser;
+pub mod presets;
+pub mod privilege;
+pub mod status;
+pub mod template;
+pub mod trust;
 
 use std::path::PathBuf;
 use std::sync::LazyLock;
 
-pub use package_manager::PackageManager;
-pub use package_system::{EXCLUDED_PACKAGES_FILENAME, PACKAGE_MANAGER_FILENAME, PACKAGES_FILENAME, PackageSystem, new_package_system};
+pub use all::{Operation, Summary, dependency_order, run_on_all};
+#[cfg(test)]
+pub use backend::MemoryBackend;
+pub use backend::{BackendType, PackageBackend};
+pub use check::{CheckReport, check_folder};
+pub use exclusion::{Exclusion, Exclusions};
+pub use list_parser::ListParser;
+pub use package_list::PackageList;
+pub use package_manager::{CommandBackend, InstallReport, PackageFailure, PackageManager};
+pub use package_system::{
+    COMMON_LAYER, EXCLUDED_PACKAGES_FILENAME, LAYERS_FOLDERNAME, PACKAGE_MANAGER_FILENAME, PACKAGES_FILENAME, PackageSystem, PackageSystemSettings,
+    SNAPSHOTS_FOLDERNAME, new_package_system,
+};
 pub use parser::*;
+pub use privilege::Privilege;
+pub use status::{Status, UploadPlan};
+pub use template::{TemplateContext, VARIABLES_FILENAME};
+pub use trust::{TRUST_FILE, TRUST_FILENAME, Trust, TrustStore};
 
 use super::CONFIG_DIR;
 
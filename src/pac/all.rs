@@ -0,0 +1,164 @@
+//! Operations that run on every package system at once.
+use std::collections::{BTreeSet, HashMap};
+use std::fmt;
+
+use itertools::Itertools;
+
+use super::package_manager::PackageManager;
+use super::package_system::PackageSystem;
+use crate::Error;
+
+#[cfg(test)]
+mod tests;
+
+/// An operation that can be run on a `PackageSystem`.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub enum Operation {
+    Install,
+    Upload,
+    Sync,
+}
+impl Operation {
+    /// Run the operation on `package_system`.
+    ///
+    /// # Errors
+    /// - Any errors from the corresponding `PackageSystem` method.
+    pub fn run(self, package_system: &mut PackageSystem) -> Result<(), Error> {
+        match self {
+            Operation::Install => package_system.install(),
+            Operation::Upload => package_system.upload(),
+            Operation::Sync => package_system.sync(),
+        }
+        .map(|_| ())
+    }
+}
+
+/// The outcome of running an `Operation` on several package systems.
+pub struct Summary {
+    /// Each package system's name and result, in the order they were run.
+    pub results: Vec<(String, Result<(), Error>)>,
+}
+impl Summary {
+    /// Whether the operation succeeded for every package system.
+    pub fn is_success(&self) -> bool {
+        self.results.iter().all(|(_, result)| result.is_ok())
+    }
+
+    /// Get the names of the package systems the operation failed for.
+    pub fn failed(&self) -> Vec<&str> {
+        self.results
+            .iter()
+            .filter(|(_, result)| result.is_err())
+            .map(|(name, _)| name.as_str())
+            .collect()
+    }
+}
+impl fmt::Display for Summary {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "Summary:")?;
+        for (name, result) in &self.results {
+            match result {
+                Ok(()) => writeln!(f, "  {}: ok", name)?,
+                Err(e) => writeln!(f, "  {}: failed: {}", name, e)?,
+            }
+        }
+        Ok(())
+    }
+}
+
+/// Run `operation` on every package system in dependency order, continuing
+/// past failures. Package systems that require a failed package system are
+/// skipped.
+///
+/// # Errors
+/// - Any errors from `dependency_order()`.
+//# UNIT TESTED
+pub fn run_on_all(package_systems: &mut HashMap<String, PackageSystem>, operation: Operation) -> Result<Summary, Error> {
+    let order = dependency_order(package_systems)?;
+    refresh_credentials(package_systems);
+    let mut results: Vec<(String, Result<(), Error>)> = Vec::new();
+    for name in order {
+        println!("==> {}", name);
+        let package_system = package_systems.get_mut(&name).unwrap();
+        let failed_requirements = package_system
+            .settings()
+            .requires
+            .iter()
+            .filter(|required| results.iter().any(|(name, result)| name == *required && result.is_err()))
+            .join(", ");
+        let result = if failed_requirements.is_empty() {
+            operation.run(package_system)
+        } else {
+            Err(Error::Failed(format!("Skipped because required package systems failed: {}", failed_requirements)))
+        };
+        if let Err(e) = &result {
+            eprintln!("{}", e);
+        }
+        results.push((name, result));
+    }
+    Ok(Summary { results })
+}
+
+/// Ask for the credentials of every privilege escalation program the package
+/// systems use, so they aren't asked for again during the run. Failures are
+/// only warnings, since each command will ask again anyway.
+fn refresh_credentials(package_systems: &HashMap<String, PackageSystem>) {
+    let privileges = package_systems
+        .values()
+        .map(PackageSystem::package_manager)
+        .filter(|package_manager| package_manager.needs_root())
+        .map(PackageManager::privilege)
+        .unique_by(|privilege| privilege.program())
+        .collect::<Vec<_>>();
+    for privilege in privileges {
+        if let Err(e) = privilege.refresh_credentials() {
+            eprintln!("Warning: {}", e);
+        }
+    }
+}
+
+/// Sort the package systems' names so that every package system comes after
+/// the package systems it `requires`. Independent package systems are sorted
+/// by name.
+///
+/// # Errors
+/// - A package system requires a package system that does not exist.
+/// - The requirements contain a cycle.
+//# INTEGRATION TESTED
+pub fn dependency_order(package_systems: &HashMap<String, PackageSystem>) -> Result<Vec<String>, Error> {
+    // check for missing requirements
+    for package_system in package_systems.values().sorted_by_key(|system| system.name()) {
+        if let Some(missing) = package_system
+            .settings()
+            .requires
+            .iter()
+            .find(|required| !package_systems.contains_key(*required))
+        {
+            return Err(Error::config(format!("Package system `{}` requires `{}`, which does not exist", package_system.name(), missing)));
+        }
+    }
+    let mut order = Vec::new();
+    let mut remaining = package_systems.keys().map(String::as_str).collect::<BTreeSet<_>>();
+    while !remaining.is_empty() {
+        // find every package system whose requirements have all run
+        let ready = remaining
+            .iter()
+            .copied()
+            .filter(|name| {
+                package_systems[*name]
+                    .settings()
+                    .requires
+                    .iter()
+                    .all(|required| !remaining.contains(required.as_str()))
+            })
+            .collect::<Vec<_>>();
+        if ready.is_empty() {
+            return Err(Error::config(format!("Package system requirements contain a cycle between: {}", remaining.iter().join(", "))));
+        }
+        for name in ready {
+            remaining.remove(name);
+            order.push(name.to_owned());
+        }
+    }
+    Ok(order)
+}
@@ -0,0 +1,40 @@
+use std::fs;
+use std::process::Command;
+
+use test_utils::TempFolder;
+
+use super::*;
+use crate::pac::PackageManager;
+
+/// Tests `run_on_all()`.
+#[cfg(test)]
+mod running {
+    use super::*;
+
+    /// A failing package system doesn't stop the others from running.
+    #[test]
+    fn continues_after_failure() {
+        let temp_folder = TempFolder::new("run_on_all");
+        let folder = temp_folder.path();
+        let mut package_systems = HashMap::new();
+        for (name, list_program) in [("a", "definitely-not-a-real-program"), ("b", "printf")] {
+            let system_folder = folder.join(name);
+            fs::create_dir_all(&system_folder).unwrap();
+            fs::write(system_folder.join(crate::pac::EXCLUDED_PACKAGES_FILENAME), "").unwrap();
+            let mut list_command = Command::new(list_program);
+            list_command.arg("nano\n");
+            let package_manager = PackageManager::build(Command::new(""), list_command);
+            package_systems.insert(name.to_string(), PackageSystem::build(name.to_string(), &system_folder, package_manager));
+        }
+
+        let summary = run_on_all(&mut package_systems, Operation::Upload).unwrap();
+        assert!(!summary.is_success());
+        assert_eq!(summary.failed(), ["a"]);
+        assert_eq!(summary.results.iter().map(|(name, _)| name.as_str()).collect::<Vec<_>>(), ["a", "b"]);
+        let host_layer = folder
+            .join("b")
+            .join(crate::pac::LAYERS_FOLDERNAME)
+            .join(format!("{}.txt", package_systems["b"].host_layer()));
+        assert_eq!(fs::read_to_string(host_layer).unwrap(), "nano\n");
+    }
+}
@@ -0,0 +1,114 @@
+//! The backends that do the work behind a `PackageManager`.
+//!
+//! A package manager file picks its backend with the top-level `type` key:
+//! - `command` (the default) - Runs the commands in the file (see
+//!   `CommandBackend`).
+//!
+//! Tests can also pick `memory`, which keeps the installed packages in memory
+//! (see `MemoryBackend`).
+#[cfg(test)]
+use std::collections::BTreeSet;
+use std::collections::HashSet;
+use std::ffi::OsStr;
+
+use serde_derive::Deserialize;
+
+use super::package_manager::InstallReport;
+use super::privilege::Privilege;
+use crate::Error;
+
+#[cfg(test)]
+mod tests;
+
+/// The backend types a package manager file can choose with `type`.
+#[derive(Deserialize, Clone, Copy, Debug, Default, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum BackendType {
+    #[default]
+    Command,
+    #[cfg(test)]
+    Memory,
+}
+
+/// Lists and installs packages for a `PackageManager`. Only `list()` and
+/// `install()` are required.
+pub trait PackageBackend {
+    /// List the installed packages.
+    fn list(&mut self) -> Result<HashSet<String>, Error>;
+
+    /// Install `packages`, reporting the packages that failed instead of
+    /// stopping at the first failure.
+    fn install(&mut self, packages: &[String]) -> Result<InstallReport, Error>;
+
+    /// Install the package manager itself if it is missing. Does nothing by
+    /// default.
+    fn bootstrap(&mut self) -> Result<(), Error> {
+        Ok(())
+    }
+
+    /// Whether the package manager can be used on this machine, without
+    /// running anything. Always `true` by default.
+    fn is_available(&self) -> bool {
+        true
+    }
+
+    /// Get the programs the backend runs, to check that they're on the
+    /// `PATH`. Empty by default.
+    fn programs(&self) -> Vec<&OsStr> {
+        Vec::new()
+    }
+
+    /// Whether anything the backend runs needs root. `false` by default.
+    fn needs_root(&self) -> bool {
+        false
+    }
+
+    /// Get how the backend runs commands that need root. `Privilege::None` by
+    /// default.
+    fn privilege(&self) -> Privilege {
+        Privilege::None
+    }
+
+    /// Run the commands that need root with `privilege`. Does nothing by
+    /// default.
+    fn set_privilege(&mut self, _privilege: Privilege) {}
+
+    /// Describe anything the user should know about how the backend runs, such
+    /// as commands that run as root. Empty by default.
+    fn notes(&self) -> Vec<String> {
+        Vec::new()
+    }
+}
+
+/// A backend that keeps the installed packages in memory, starting from the
+/// optional `installed` array. Nothing on the machine changes, so it is only
+/// built for tests.
+#[cfg(test)]
+#[derive(Deserialize, Debug, Default, PartialEq)]
+pub struct MemoryBackend {
+    #[serde(default)]
+    installed: BTreeSet<String>,
+}
+#[cfg(test)]
+impl MemoryBackend {
+    /// Build a `MemoryBackend` with `installed` already installed.
+    pub fn new(installed: impl IntoIterator<Item = impl Into<String>>) -> Self {
+        MemoryBackend {
+            installed: installed.into_iter().map(Into::into).collect(),
+        }
+    }
+}
+#[cfg(test)]
+impl PackageBackend for MemoryBackend {
+    fn list(&mut self) -> Result<HashSet<String>, Error> {
+        Ok(self.installed.iter().cloned().collect())
+    }
+
+    fn install(&mut self, packages: &[String]) -> Result<InstallReport, Error> {
+        self.installed.extend(packages.iter().cloned());
+        Ok(InstallReport {
+            installed: packages.to_vec(),
+            ..Default::default()
+        })
+    }
+}
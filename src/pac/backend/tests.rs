@@ -0,0 +1,17 @@
+use super::*;
+
+/// Tests `MemoryBackend`.
+#[cfg(test)]
+mod memory {
+    use super::*;
+
+    /// Installed packages are listed along with the packages the backend started
+    /// with.
+    #[test]
+    fn install_and_list() {
+        let mut backend = MemoryBackend::new(["git"]);
+        let report = backend.install(&[String::from("vim"), String::from("git")]).unwrap();
+        assert!(report.is_success());
+        assert_eq!(backend.list().unwrap(), HashSet::from(["git", "vim"].map(String::from)));
+    }
+}
@@ -0,0 +1,279 @@
+//! Strict validation of package system folders for `pac check`.
+use std::collections::HashMap;
+use std::fmt;
+use std::fs;
+use std::path::Path;
+
+use itertools::Itertools;
+
+use super::exclusion::Exclusions;
+use super::package_list::PackageList;
+use super::package_system::{PACKAGE_MANAGER_FILENAME, PackageSystem};
+use super::parser::GlobalSettings;
+use super::privilege::{Privilege, is_root};
+use super::template::TemplateContext;
+use super::trust::{Trust, TrustStore};
+use crate::Error;
+
+#[cfg(test)]
+mod tests;
+
+/// Keys allowed at the top level of a package manager file, including the
+/// `PackageSystemSettings` keys.
+const TOP_LEVEL_KEYS: [&str; 9] = [
+    "type",
+    "install_command",
+    "list_command",
+    "check_command",
+    "bootstrap_command",
+    "list_parser",
+    "default",
+    "requires",
+    "privilege",
+];
+/// Keys allowed at the top level of a package manager file with a `memory`
+/// backend, including the `PackageSystemSettings` keys.
+#[cfg(test)]
+const MEMORY_TOP_LEVEL_KEYS: [&str; 5] = ["type", "installed", "default", "requires", "privilege"];
+/// Tables at the top level that hold a command.
+const COMMAND_TABLES: [&str; 4] = ["install_command", "list_command", "check_command", "bootstrap_command"];
+/// Keys allowed in a command table.
+const COMMAND_KEYS: [&str; 8] = ["command", "args", "shell", "env", "cwd", "stdin", "per_package", "root"];
+
+/// The outcome of checking one package system folder.
+#[derive(Debug, Default)]
+pub struct CheckReport {
+    /// The package system's name.
+    pub name: String,
+    /// Problems that stop the package system from working correctly.
+    pub problems: Vec<String>,
+    /// Things worth knowing that aren't problems, such as commands that run
+    /// through the shell.
+    pub notes: Vec<String>,
+}
+impl CheckReport {
+    /// Whether no problems were found.
+    pub fn is_ok(&self) -> bool {
+        self.problems.is_empty()
+    }
+}
+impl fmt::Display for CheckReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.problems.len() {
+            0 => writeln!(f, "{}: ok", self.name)?,
+            1 => writeln!(f, "{}: 1 problem", self.name)?,
+            count => writeln!(f, "{}: {} problems", self.name, count)?,
+        }
+        for problem in &self.problems {
+            writeln!(f, "  error: {}", problem)?;
+        }
+        for note in &self.notes {
+            writeln!(f, "  note: {}", note)?;
+        }
+        Ok(())
+    }
+}
+
+/// Check every package system folder in `folder`, sorted by name. Problems in
+/// a package system are reported instead of stopping the check. If `trust` is
+/// given, list commands are only run for package systems whose commands are
+/// approved in it.
+///
+/// # Errors
+/// - Any errors from `TemplateContext::detect_with_variables()`.
+/// - Any errors from `GlobalSettings::from_folder()`.
+/// - `folder` cannot be read.
+//# INTEGRATION TESTED
+pub fn check_folder(folder: impl AsRef<Path>, trust: Option<&TrustStore>) -> Result<Vec<CheckReport>, Error> {
+    let folder = folder.as_ref();
+    let context = TemplateContext::detect_with_variables(folder)?;
+    let global_settings = GlobalSettings::from_folder(folder)?;
+    let entries = fs::read_dir(folder).map_err(|e| Error::io("read package systems folder", folder, e))?;
+    let mut package_system_folders = Vec::new();
+    for entry in entries {
+        let path = entry.map_err(|e| Error::io("read package systems folder", folder, e))?.path();
+        if path.is_dir() {
+            package_system_folders.push(path);
+        }
+    }
+    package_system_folders.sort();
+    Ok(package_system_folders
+        .iter()
+        .map(|path| check_package_system(path, &context, &global_settings, trust))
+        .collect())
+}
+
+/// Check the package system folder at `folder`: unknown keys, programs that
+/// aren't on the `PATH`, unreadable package files, duplicated packages, and
+/// whether the list command's output parses. The list command is skipped, with
+/// a note, if its commands aren't approved in `trust`.
+//# UNIT TESTED
+pub fn check_package_system(folder: &Path, context: &TemplateContext, global_settings: &GlobalSettings, trust: Option<&TrustStore>) -> CheckReport {
+    let mut report = CheckReport {
+        name: folder.file_name().unwrap_or_default().to_string_lossy().to_string(),
+        ..Default::default()
+    };
+    // look for typos first, since they often cause the errors below
+    let package_manager_file = folder.join(PACKAGE_MANAGER_FILENAME);
+    if let Ok(template) = fs::read_to_string(&package_manager_file)
+        && let Ok(contents) = context.render(&template)
+        && let Ok(keys) = unknown_keys(&contents)
+    {
+        report.problems.extend(keys.iter().map(|key| format!("unknown key `{}`", key)));
+    }
+    let mut package_system = match PackageSystem::from_folder_with_context(folder, context) {
+        Ok(package_system) => package_system,
+        Err(e) => {
+            report.problems.push(e.to_string());
+            return report;
+        }
+    };
+    global_settings.apply(&mut package_system, &context.hostname);
+    check_programs(&package_system, &mut report);
+    check_package_files(&package_system, &mut report);
+    if let Some(trust) = trust
+        && trust.verify(&package_system) != Trust::Trusted
+    {
+        // not a problem with the config, so a fresh setup can still pass
+        report
+            .notes
+            .push(String::from("the commands are not approved, so the list command was not run. Run another `pac` command to review them"));
+        return report;
+    }
+    match package_system.package_manager_mut().list() {
+        Ok(packages) => report.notes.push(format!("the list command found {} installed packages", packages.len())),
+        Err(e) => report.problems.push(format!("the list command failed: {}", e)),
+    }
+    report
+}
+
+/// Helper function to report the programs that aren't on the `PATH`, commands
+/// that need root but can't get it, and the package manager's notes, such as
+/// the commands that run through the shell or as root.
+fn check_programs(package_system: &PackageSystem, report: &mut CheckReport) {
+    let package_manager = package_system.package_manager();
+    let mut programs = package_manager.programs();
+    if package_manager.needs_root() {
+        match package_manager.privilege().program() {
+            Some(program) => programs.push(program.as_ref()),
+            // `Privilege::Auto` would silently run the commands without root
+            None if package_manager.privilege() == Privilege::Auto && !is_root() => report
+                .problems
+                .push(String::from("some commands need root, but none of `sudo`, `doas` and `run0` is on the PATH")),
+            None => {}
+        }
+    }
+    for program in programs.into_iter().unique() {
+        if utils::find_program(program).is_none() {
+            report
+                .problems
+                .push(format!("program `{}` is not on the PATH", program.to_string_lossy()));
+        }
+    }
+    report.notes.extend(package_manager.notes());
+}
+
+/// Helper function to report package files that can't be read and packages
+/// that are listed more than once.
+fn check_package_files(package_system: &PackageSystem, report: &mut CheckReport) {
+    let mut recorded_in: HashMap<String, &str> = HashMap::new();
+    for layer in package_system.layers() {
+        let Some(package_list) = read_package_file(&package_system.layer_file(layer), report) else {
+            continue;
+        };
+        for package in package_list.duplicates() {
+            report
+                .problems
+                .push(format!("`{}` is listed more than once in the `{}` layer", package, layer));
+        }
+        for package in package_list.packages() {
+            if let Some(other_layer) = recorded_in.get(&package) {
+                report
+                    .problems
+                    .push(format!("`{}` is in both the `{}` and `{}` layers", package, other_layer, layer));
+            } else {
+                recorded_in.insert(package, layer);
+            }
+        }
+    }
+    if let Some(excluded) = read_package_file(package_system.excluded_packages_file(), report) {
+        for package in excluded.duplicates() {
+            report.problems.push(format!("`{}` is excluded more than once", package));
+        }
+        if let Err(e) = Exclusions::from_list(&excluded) {
+            report.problems.push(e.in_file(package_system.excluded_packages_file()).to_string());
+        }
+    }
+}
+
+/// Helper function to read the package file at `path`, recording a problem in
+/// `report` if it can't be read. Missing files are fine, since they are
+/// created when needed.
+fn read_package_file(path: &Path, report: &mut CheckReport) -> Option<PackageList> {
+    let contents = match fs::read_to_string(path) {
+        Ok(contents) => contents,
+        Err(e) if e.kind() == std::io::ErrorKind::NotFound => return None,
+        Err(e) => {
+            report.problems.push(Error::io("read package file", path, e).to_string());
+            return None;
+        }
+    };
+    match contents.parse::<PackageList>() {
+        Ok(package_list) => Some(package_list),
+        Err(e) => {
+            report.problems.push(e.in_file(path).to_string());
+            None
+        }
+    }
+}
+
+/// Find the keys in a rendered package manager file that nothing reads, such as
+/// a misspelled `list_comand`. Nested keys are joined with `.`.
+///
+/// # Errors
+/// - `contents` is not valid TOML.
+//# UNIT TESTED
+pub fn unknown_keys(contents: &str) -> Result<Vec<String>, Error> {
+    let table = contents.parse::<toml::Table>()?;
+    let top_level_keys: &[&str] = match table.get("type").and_then(toml::Value::as_str) {
+        #[cfg(test)]
+        Some("memory") => &MEMORY_TOP_LEVEL_KEYS,
+        _ => &TOP_LEVEL_KEYS,
+    };
+    let mut unknown = Vec::new();
+    for (key, value) in &table {
+        if !top_level_keys.contains(&key.as_str()) {
+            unknown.push(key.to_owned());
+            continue;
+        }
+        let Some(inner) = value.as_table() else {
+            continue;
+        };
+        let allowed: &[&str] = if COMMAND_TABLES.contains(&key.as_str()) {
+            &COMMAND_KEYS
+        } else if key == "list_parser" {
+            list_parser_keys(inner.get("mode").and_then(toml::Value::as_str).unwrap_or_default())
+        } else {
+            continue;
+        };
+        unknown.extend(
+            inner
+                .keys()
+                .filter(|inner_key| !allowed.contains(&inner_key.as_str()))
+                .map(|inner_key| format!("{}.{}", key, inner_key)),
+        );
+    }
+    Ok(unknown)
+}
+
+/// Helper function to get the keys allowed in a `list_parser` table with `mode`.
+/// Unknown modes are reported when parsing, so every key is allowed.
+fn list_parser_keys(mode: &str) -> &'static [&'static str] {
+    match mode {
+        "whitespace" | "lines" => &["mode"],
+        "regex" => &["mode", "pattern", "group"],
+        "column" => &["mode", "column", "delimiter", "skip_lines"],
+        "json" => &["mode", "pointer", "field"],
+        _ => &["mode", "pattern", "group", "column", "delimiter", "skip_lines", "pointer", "field"],
+    }
+}
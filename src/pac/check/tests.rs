@@ -0,0 +1,115 @@
+use test_utils::TempFolder;
+
+use super::*;
+use crate::pac::presets::PRESETS;
+
+/// Tests `unknown_keys()`.
+#[cfg(test)]
+mod keys {
+    use super::*;
+
+    /// The presets only use known keys.
+    #[test]
+    fn presets() {
+        for (name, contents) in PRESETS {
+            assert!(unknown_keys(contents).unwrap().is_empty(), "{}", name);
+        }
+    }
+
+    /// Misspelled keys are found at the top level and in tables.
+    #[test]
+    fn typos() {
+        let contents = "default = true\n\n[install_command]\ncommand = \"true\"\narg = []\n\n[list_comand]\ncommand = \"true\"\n\n[list_parser]\nmode = \"regex\"\npattern = '(.*)'\ncolumn = 1\n";
+        assert_eq!(unknown_keys(contents).unwrap(), ["install_command.arg", "list_comand", "list_parser.column"]);
+        // environment variables can have any name
+        assert!(
+            unknown_keys("[install_command]\ncommand = \"true\"\nenv = { ANYTHING = \"1\" }\n")
+                .unwrap()
+                .is_empty()
+        );
+        assert!(unknown_keys("[install_command\n").is_err());
+        // the memory backend has its own keys
+        assert_eq!(unknown_keys("type = \"memory\"\ninstalled = []\n").unwrap(), Vec::<String>::new());
+        assert_eq!(unknown_keys("type = \"memory\"\n\n[list_command]\ncommand = \"true\"\n").unwrap(), ["list_command"]);
+    }
+}
+
+/// Tests `check_package_system()`.
+#[cfg(test)]
+mod package_system {
+    use super::*;
+
+    /// Write a package system folder named `name` with a package manager file
+    /// made of `contents`.
+    fn package_system_folder(name: &str, contents: &str) -> TempFolder {
+        let folder = TempFolder::new(name);
+        fs::write(folder.path().join(PACKAGE_MANAGER_FILENAME), contents).unwrap();
+        folder
+    }
+
+    /// A working package system only gets notes.
+    #[test]
+    fn ok() {
+        let folder =
+            package_system_folder("ok", "[install_command]\nshell = \"true\"\n\n[list_command]\ncommand = \"printf\"\nargs = [\"git\\nvim\\n\"]\n");
+        let report = check_package_system(folder.path(), &TemplateContext::default(), &GlobalSettings::default(), None);
+        assert!(report.is_ok(), "{}", report);
+        assert_eq!(report.notes, ["`install_command` runs through the shell", "the list command found 2 installed packages"]);
+    }
+
+    /// A list command that exits with an error is a problem, not an empty list.
+    #[test]
+    fn list_fails() {
+        let folder = package_system_folder("list_fails", "[install_command]\ncommand = \"true\"\n\n[list_command]\ncommand = \"false\"\n");
+        let report = check_package_system(folder.path(), &TemplateContext::default(), &GlobalSettings::default(), None);
+        assert_eq!(report.problems.len(), 1, "{}", report);
+        assert!(report.problems[0].starts_with("the list command failed"), "{}", report);
+        assert!(!report.notes.iter().any(|note| note.contains("installed packages")), "{}", report);
+    }
+
+    /// Every problem is reported at once.
+    #[test]
+    fn problems() {
+        let folder = package_system_folder(
+            "problems",
+            "[install_command]\ncommand = \"definitely-not-a-real-program\"\nroot = true\n\n[list_command]\ncommand = \"true\"\nargs = []\nshel = \"\"\n",
+        );
+        fs::write(folder.path().join(crate::pac::PACKAGES_FILENAME), "git\nvim\ngit\n").unwrap();
+        fs::write(folder.path().join(crate::pac::EXCLUDED_PACKAGES_FILENAME), "re:(\n").unwrap();
+        let global_settings = GlobalSettings {
+            privilege: Some(crate::pac::Privilege::None),
+            ..Default::default()
+        };
+        let report = check_package_system(folder.path(), &TemplateContext::default(), &global_settings, None);
+        assert_eq!(report.problems.len(), 4, "{}", report);
+        assert_eq!(report.problems[0], "unknown key `list_command.shel`");
+        assert_eq!(report.problems[1], "program `definitely-not-a-real-program` is not on the PATH");
+        assert_eq!(report.problems[2], "`git` is listed more than once in the `common` layer");
+        assert!(report.problems[3].contains("Invalid exclusion regex"), "{}", report);
+    }
+
+    /// A package manager file that doesn't load is still checked for typos.
+    #[test]
+    fn invalid() {
+        let folder = package_system_folder("invalid", "[install_command]\ncommand = \"true\"\n\n[list_comand]\ncommand = \"true\"\n");
+        let report = check_package_system(folder.path(), &TemplateContext::default(), &GlobalSettings::default(), None);
+        assert_eq!(report.problems.len(), 2, "{}", report);
+        assert_eq!(report.problems[0], "unknown key `list_comand`");
+        assert!(report.problems[1].contains("list_command"), "{}", report);
+    }
+
+    /// The list command only runs once the commands are approved.
+    #[test]
+    fn untrusted() {
+        let folder = package_system_folder("untrusted", "[install_command]\ncommand = \"true\"\n\n[list_command]\ncommand = \"true\"\n");
+        let mut trust = TrustStore::default();
+        let report = check_package_system(folder.path(), &TemplateContext::default(), &GlobalSettings::default(), Some(&trust));
+        assert!(report.is_ok(), "{}", report);
+        assert!(report.notes.iter().any(|note| note.contains("not approved")), "{}", report);
+
+        trust.approve(&PackageSystem::from_folder_with_context(folder.path(), &TemplateContext::default()).unwrap());
+        let report = check_package_system(folder.path(), &TemplateContext::default(), &GlobalSettings::default(), Some(&trust));
+        assert!(report.is_ok(), "{}", report);
+        assert_eq!(report.notes, ["the list command found 0 installed packages"]);
+    }
+}
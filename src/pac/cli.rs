@@ -1,21 +1,49 @@
+use std::collections::HashMap;
+use std::io::{self, IsTerminal};
+
+use clap::builder::PossibleValuesParser;
 use clap::{Args, Subcommand};
+use itertools::Itertools;
+
+use super::presets::preset_names;
+use super::{
+    Operation, PAC_DIR, PackageSystem, TRUST_FILE, Trust, TrustStore, check_folder, default_package_system_name, new_package_system,
+    package_systems_from_folder, run_on_all, trust,
+};
+use crate::Error;
 
 /// `pac`-level subcommands.
 #[derive(Subcommand)]
 pub enum PacCommand {
     /// Install packages from a package list
     #[command(visible_alias = "in")]
-    Install(PackageManagerArgs),
+    Install(OperationArgs),
     /// Save the package manager state
     #[command(visible_alias = "up")]
-    Upload(PackageManagerArgs),
+    Upload(UploadArgs),
     /// `install`, then `upload`
-    Sync(PackageManagerArgs),
-    /// Exclude packages from syncing
+    Sync(UploadArgs),
+    /// Show how the recorded packages differ from the installed packages
+    #[command(visible_alias = "st")]
+    Status {
+        #[command(flatten)]
+        args: PackageManagerArgs,
+        /// Print the status as JSON
+        #[arg(long)]
+        json: bool,
+    },
+    /// Exclude packages from syncing. Accepts globs like `nvidia-*` and
+    /// regexes like `re:.*-debug`
     #[command(visible_alias = "ex")]
     Exclude {
-        /// Packages to exclude
+        /// Packages or patterns to exclude
+        #[arg(required_unless_present = "test")]
         packages: Vec<String>,
+        /// Show which installed packages a pattern would exclude without excluding anything
+        #[arg(long, value_name = "PATTERN", conflicts_with = "packages")]
+        test: Option<String>,
+        #[command(flatten)]
+        args: PackageManagerArgs,
     },
     /// Reinclude previously excluded packages
     #[command(visible_alias = "re")]
@@ -23,9 +51,48 @@ pub enum PacCommand {
         /// Packages to reinclude
         #[arg(required = true)]
         packages: Vec<String>,
+        #[command(flatten)]
+        args: PackageManagerArgs,
+    },
+    /// Create a new package system
+    New {
+        /// The name of the new package system
+        name: String,
+        /// Fill in the package manager file for a common package manager
+        #[arg(long, value_parser = PossibleValuesParser::new(preset_names()))]
+        preset: Option<String>,
+    },
+    /// Validate every package system folder
+    Check,
+    /// Run an operation on every package system
+    All {
+        #[command(subcommand)]
+        operation: AllCommand,
     },
 }
 
+/// Operations that can be run on every package system.
+#[derive(Subcommand, Clone, Copy)]
+pub enum AllCommand {
+    /// Install packages for every package system
+    #[command(visible_alias = "in")]
+    Install,
+    /// Save the state of every package system
+    #[command(visible_alias = "up")]
+    Upload,
+    /// `install`, then `upload` for every package system
+    Sync,
+}
+impl From<AllCommand> for Operation {
+    fn from(command: AllCommand) -> Self {
+        match command {
+            AllCommand::Install => Operation::Install,
+            AllCommand::Upload => Operation::Upload,
+            AllCommand::Sync => Operation::Sync,
+        }
+    }
+}
+
 /// Package manager name.
 #[derive(Args, Debug)]
 pub struct PackageManagerArgs {
@@ -34,10 +101,180 @@ pub struct PackageManagerArgs {
     package_manager_name: Option<String>,
 }
 
-pub fn process_command(command: &PacCommand) -> Result<(), String> {
+/// Arguments for `install`, `upload` and `sync`.
+#[derive(Args, Debug)]
+pub struct OperationArgs {
+    #[command(flatten)]
+    package_manager_args: PackageManagerArgs,
+    /// Show what would change without changing anything
+    #[arg(long)]
+    dry_run: bool,
+}
+
+/// Arguments for `upload` and `sync`.
+#[derive(Args, Debug)]
+pub struct UploadArgs {
+    #[command(flatten)]
+    operation_args: OperationArgs,
+    /// The layer to record new packages in, such as `common` or a profile.
+    /// Defaults to the host layer
+    #[arg(long)]
+    layer: Option<String>,
+}
+
+/// Run a `pac` subcommand against the package systems in `PAC_DIR`. Package
+/// systems whose commands changed since they were approved are shown to the
+/// user first, or refused if `refuse_untrusted` is set.
+///
+/// # Errors
+/// - The package systems could not be loaded.
+/// - The requested package system does not exist.
+/// - Any errors from `approve_commands()`.
+/// - Any errors from the `PackageSystem` operation.
+pub fn process_command(command: &PacCommand, refuse_untrusted: bool) -> Result<(), Error> {
     match command {
-        PacCommand::Install(args) | PacCommand::Upload(args) | PacCommand::Sync(args) => println!("{:?}", args),
-        PacCommand::Exclude { packages } | PacCommand::Reinclude { packages } => println!("{:?}", packages),
+        PacCommand::Install(args) => run_operation(args, None, Operation::Install, refuse_untrusted)?,
+        PacCommand::Upload(args) => run_operation(&args.operation_args, args.layer.as_deref(), Operation::Upload, refuse_untrusted)?,
+        PacCommand::Sync(args) => run_operation(&args.operation_args, args.layer.as_deref(), Operation::Sync, refuse_untrusted)?,
+        PacCommand::Status { args, json } => {
+            let status = select_package_system(&mut load_package_systems()?, args, refuse_untrusted)?.status()?;
+            if *json {
+                println!("{}", serde_json::to_string_pretty(&status).expect("status is always serializable"));
+            } else {
+                println!("{}", status);
+            }
+        }
+        PacCommand::Exclude {
+            test: Some(pattern), args, ..
+        } => {
+            let matches = select_package_system(&mut load_package_systems()?, args, refuse_untrusted)?.test_exclusion(pattern)?;
+            println!("`{}` matches {} installed packages", pattern, matches.len());
+            for package in matches {
+                println!("  {}", package);
+            }
+        }
+        PacCommand::Exclude { packages, args, .. } => {
+            select_package_system(&mut load_package_systems()?, args, refuse_untrusted)?.exclude(packages)?;
+        }
+        PacCommand::Reinclude { packages, args } => {
+            select_package_system(&mut load_package_systems()?, args, refuse_untrusted)?.reinclude(packages)?;
+        }
+        PacCommand::New { name, preset } => new_package_system(PAC_DIR.as_path(), name, preset.as_deref())?,
+        PacCommand::Check => {
+            // never prompt, just skip the list commands that aren't approved
+            let reports = check_folder(PAC_DIR.as_path(), Some(&TrustStore::load(TRUST_FILE.as_path())?))?;
+            if reports.is_empty() {
+                println!("No package systems found");
+            }
+            for report in &reports {
+                print!("{}", report);
+            }
+            let failed = reports
+                .iter()
+                .filter(|report| !report.is_ok())
+                .map(|report| report.name.as_str())
+                .collect::<Vec<_>>();
+            if !failed.is_empty() {
+                return Err(Error::config(format!("Problems found in package systems: {}", failed.join(", "))));
+            }
+        }
+        PacCommand::All { operation } => {
+            let mut package_systems = load_package_systems()?;
+            approve_commands(package_systems.values(), refuse_untrusted)?;
+            let summary = run_on_all(&mut package_systems, (*operation).into())?;
+            print!("{}", summary);
+            if !summary.is_success() {
+                return Err(Error::Failed(format!("Failed for package systems: {}", summary.failed().join(", "))));
+            }
+        }
+    }
+    Ok(())
+}
+
+/// Run `operation` on the package system selected by `args`, or only print
+/// what it would change if `--dry-run` was given. New packages are uploaded
+/// to `layer` if given.
+fn run_operation(args: &OperationArgs, layer: Option<&str>, operation: Operation, refuse_untrusted: bool) -> Result<(), Error> {
+    let mut package_systems = load_package_systems()?;
+    let package_system = select_package_system(&mut package_systems, &args.package_manager_args, refuse_untrusted)?;
+    if let Some(layer) = layer {
+        package_system.set_upload_layer(layer)?;
+    }
+    if args.dry_run {
+        println!("{}", package_system.plan(operation)?);
+        return Ok(());
+    }
+    operation.run(package_system)
+}
+
+/// Load every package system in `PAC_DIR`.
+fn load_package_systems() -> Result<HashMap<String, PackageSystem>, Error> {
+    package_systems_from_folder(PAC_DIR.as_path())
+}
+
+/// Pick the package system requested by `args` out of `package_systems` and
+/// make sure its commands are approved. If no name was given, the default
+/// package system is used.
+///
+/// # Errors
+/// - No package system has the requested name.
+/// - Any errors from `default_package_system_name()`.
+/// - Any errors from `approve_commands()`.
+fn select_package_system<'a>(
+    package_systems: &'a mut HashMap<String, PackageSystem>,
+    args: &PackageManagerArgs,
+    refuse_untrusted: bool,
+) -> Result<&'a mut PackageSystem, Error> {
+    let name = match &args.package_manager_name {
+        Some(name) => name.to_owned(),
+        None => default_package_system_name(package_systems)?,
+    };
+    let available = package_systems.keys().sorted().join(", ");
+    let package_system = package_systems
+        .get_mut(&name)
+        .ok_or_else(|| Error::Usage(format!("Package system `{}` does not exist. Available: {}", name, available)))?;
+    approve_commands([&*package_system], refuse_untrusted)?;
+    Ok(package_system)
+}
+
+/// Make sure the commands of every package system in `package_systems` are
+/// approved in `TRUST_FILE` before any of them run. Commands that are new or
+/// changed are shown, and the user is asked to approve them unless
+/// `refuse_untrusted` is set or there is no terminal to ask on.
+///
+/// # Errors
+/// - Any errors from `TrustStore::load()` or `TrustStore::save()`.
+/// - The user didn't approve a package system's commands.
+/// - The commands weren't approved and the user couldn't be asked.
+fn approve_commands<'a>(package_systems: impl IntoIterator<Item = &'a PackageSystem>, refuse_untrusted: bool) -> Result<(), Error> {
+    let mut trust = TrustStore::load(TRUST_FILE.as_path())?;
+    for package_system in package_systems.into_iter().sorted_by_key(|package_system| package_system.name()) {
+        let approved = match trust.verify(package_system) {
+            Trust::Trusted => continue,
+            Trust::New => {
+                eprintln!("`{}` has commands that were never approved:", package_system.name());
+                String::new()
+            }
+            Trust::Changed { approved } => {
+                eprintln!("The commands of `{}` changed since they were approved:", package_system.name());
+                approved
+            }
+        };
+        eprintln!("{}", trust::diff(&approved, package_system.commands()));
+        if refuse_untrusted || !io::stdin().is_terminal() {
+            return Err(Error::Untrusted(format!(
+                "Refusing to run the unapproved commands of `{}`. Run `pac` in a terminal without `--refuse-untrusted` to review them",
+                package_system.name()
+            )));
+        }
+        eprint!("Approve these commands? [y/N] ");
+        let mut answer = String::new();
+        io::stdin().read_line(&mut answer).map_err(|e| Error::io("read", "stdin", e))?;
+        if !matches!(answer.trim().to_lowercase().as_str(), "y" | "yes") {
+            return Err(Error::Untrusted(format!("The commands of `{}` were not approved", package_system.name())));
+        }
+        trust.approve(package_system);
+        trust.save()?;
     }
     Ok(())
 }
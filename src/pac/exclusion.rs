@@ -0,0 +1,109 @@
+//! Exact names and patterns in the excluded packages file.
+use std::collections::HashSet;
+
+use regex::Regex;
+
+use super::package_list::PackageList;
+use crate::Error;
+
+#[cfg(test)]
+mod tests;
+
+/// Marks an excluded packages file line as a regex.
+pub const REGEX_PREFIX: &str = "re:";
+
+/// A line in the excluded packages file.
+///
+/// # Syntax
+/// - `re:<regex>` - a regex that must match the whole package name. The rest
+///   of the line is the regex, including any `#` or whitespace.
+/// - A line containing `*` or `?` is a glob. `*` matches any number of
+///   characters and `?` matches one character.
+/// - Anything else is an exact package name.
+///
+/// ## Syntax Example
+/// <pre>
+/// intel-ucode
+/// nvidia-*
+/// re:.*-(debug|dbg)
+/// </pre>
+#[derive(Debug, Clone)]
+pub enum Exclusion {
+    Exact(String),
+    Glob(Regex),
+    Regex(Regex),
+}
+impl Exclusion {
+    /// Parse an excluded packages file line.
+    ///
+    /// # Errors
+    /// - The line is a regex that does not compile.
+    //# UNIT TESTED
+    pub fn parse(line: &str) -> Result<Self, Error> {
+        if let Some(regex) = line.strip_prefix(REGEX_PREFIX) {
+            return anchored(regex)
+                .map(Exclusion::Regex)
+                .map_err(|e| Error::config(format!("Invalid exclusion regex `{}`: {}", regex, e)));
+        }
+        if line.contains(['*', '?']) {
+            let regex = line
+                .chars()
+                .map(|character| match character {
+                    '*' => String::from(".*"),
+                    '?' => String::from("."),
+                    character => regex::escape(&character.to_string()),
+                })
+                .collect::<String>();
+            // escaping makes any glob a valid regex
+            return Ok(Exclusion::Glob(anchored(&regex).unwrap()));
+        }
+        Ok(Exclusion::Exact(line.to_string()))
+    }
+
+    /// Whether `package` is excluded by this line.
+    //# UNIT TESTED
+    pub fn matches(&self, package: &str) -> bool {
+        match self {
+            Exclusion::Exact(name) => name == package,
+            Exclusion::Glob(regex) | Exclusion::Regex(regex) => regex.is_match(package),
+        }
+    }
+
+    /// Whether this line is a glob or regex instead of an exact name.
+    pub fn is_pattern(&self) -> bool {
+        !matches!(self, Exclusion::Exact(_))
+    }
+}
+
+/// Every line of an excluded packages file.
+#[derive(Debug, Default, Clone)]
+pub struct Exclusions {
+    exclusions: Vec<Exclusion>,
+}
+impl Exclusions {
+    /// Parse every package line of an excluded packages file.
+    ///
+    /// # Errors
+    /// - Any errors from `Exclusion::parse()`.
+    //# UNIT TESTED
+    pub fn from_list(list: &PackageList) -> Result<Self, Error> {
+        let exclusions = list.package_names().map(Exclusion::parse).collect::<Result<_, _>>()?;
+        Ok(Exclusions { exclusions })
+    }
+
+    /// Whether any line excludes `package`.
+    pub fn is_excluded(&self, package: &str) -> bool {
+        self.exclusions.iter().any(|exclusion| exclusion.matches(package))
+    }
+
+    /// Get the `packages` that are excluded.
+    //# UNIT TESTED
+    pub fn matching<'a>(&self, packages: impl IntoIterator<Item = &'a String>) -> HashSet<String> {
+        packages.into_iter().filter(|package| self.is_excluded(package)).cloned().collect()
+    }
+}
+
+/// Helper function to compile a regex that must match a whole package name.
+fn anchored(regex: &str) -> Result<Regex, regex::Error> {
+    Regex::new(&format!("^(?:{})$", regex))
+}
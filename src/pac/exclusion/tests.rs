@@ -0,0 +1,43 @@
+use super::*;
+
+/// Build a `HashSet` of package names.
+fn packages(names: &[&str]) -> HashSet<String> {
+    names.iter().map(|name| name.to_string()).collect()
+}
+
+/// Tests `Exclusion::parse()` and `Exclusion::matches()`.
+#[test]
+fn parse() {
+    let exact = Exclusion::parse("intel-ucode").unwrap();
+    assert!(!exact.is_pattern());
+    assert!(exact.matches("intel-ucode"));
+    assert!(!exact.matches("intel-ucode-extra"));
+
+    let glob = Exclusion::parse("nvidia-*").unwrap();
+    assert!(glob.is_pattern());
+    assert!(glob.matches("nvidia-utils"));
+    assert!(!glob.matches("nvidia"));
+    // glob characters are the only special characters
+    let glob = Exclusion::parse("lib?++*").unwrap();
+    assert!(glob.matches("libc++abi"));
+    assert!(!glob.matches("libcxxabi"));
+
+    let regex = Exclusion::parse("re:.*-(debug|dbg)").unwrap();
+    assert!(regex.is_pattern());
+    assert!(regex.matches("glibc-debug"));
+    // regexes must match the whole name
+    assert!(!regex.matches("glibc-debug-extra"));
+
+    assert!(Exclusion::parse("re:(").is_err());
+}
+
+/// Tests `Exclusions::from_list()` and `Exclusions::matching()`.
+#[test]
+fn matching() {
+    let list = "# graphics\nnvidia-*\nintel-ucode # only on intel machines\nre:.*-debug\nre:c#-[0-9]+\n"
+        .parse::<PackageList>()
+        .unwrap();
+    let exclusions = Exclusions::from_list(&list).unwrap();
+    let installed = packages(&["nvidia-utils", "nvidia-settings", "intel-ucode", "glibc-debug", "c#-12", "c", "vim"]);
+    assert_eq!(exclusions.matching(&installed), packages(&["nvidia-utils", "nvidia-settings", "intel-ucode", "glibc-debug", "c#-12"]));
+}
@@ -0,0 +1,181 @@
+//! Parsers that turn a list command's output into package names.
+use std::collections::HashSet;
+
+use regex::Regex;
+use serde_derive::Deserialize;
+use serde_json::Value;
+
+use crate::Error;
+
+#[cfg(test)]
+mod tests;
+
+mod toml_structs {
+    //! Structs used to parse the `list_parser` table with the `toml` crate.
+    use serde_derive::Deserialize;
+
+    /// A proxy for a `ListParser`.
+    #[derive(Deserialize)]
+    #[serde(tag = "mode", rename_all = "lowercase")]
+    pub enum ListParserProxy {
+        Whitespace,
+        Lines,
+        Regex {
+            pattern: String,
+            #[serde(default = "default_group")]
+            group: usize,
+        },
+        Column {
+            column: usize,
+            delimiter: Option<String>,
+            #[serde(default)]
+            skip_lines: usize,
+        },
+        Json {
+            pointer: String,
+            field: Option<String>,
+        },
+    }
+
+    /// Use the first capture group by default.
+    fn default_group() -> usize {
+        1
+    }
+}
+use toml_structs::*;
+
+/// How to read package names from a list command's output.
+///
+/// # TOML Format
+/// A table called `list_parser` with a `mode` key set to one of:
+/// - `"whitespace"` - Packages are separated by whitespace. This is the default.
+/// - `"lines"` - Each non-empty line is a package, surrounding whitespace is trimmed.
+/// - `"regex"` - Each line matching `pattern` is a package, taken from capture
+///   group `group` (default `1`).
+/// - `"column"` - Each line is split on `delimiter` (default: whitespace) and
+///   column `column` (starting at `0`) is the package. The first `skip_lines`
+///   lines (default `0`) are skipped, for headers.
+/// - `"json"` - The output is JSON and `pointer` is a JSON pointer to either an
+///   array or an object. Array items are packages, or objects whose `field` is
+///   the package. Object keys are packages.
+///
+/// ## Format Example
+/// <pre>
+/// [list_parser]
+/// mode = "regex"
+/// pattern = '^([^/]+)/'
+/// </pre>
+#[derive(Deserialize, Debug, Default)]
+#[serde(try_from = "ListParserProxy")]
+pub enum ListParser {
+    #[default]
+    Whitespace,
+    Lines,
+    Regex {
+        regex: Regex,
+        group: usize,
+    },
+    Column {
+        column: usize,
+        delimiter: Option<String>,
+        skip_lines: usize,
+    },
+    Json {
+        pointer: String,
+        field: Option<String>,
+    },
+}
+impl ListParser {
+    /// Read the package names from `output`.
+    ///
+    /// # Errors
+    /// - The output is not valid JSON in `json` mode.
+    /// - The JSON pointer doesn't point to an array or object.
+    /// - An array item is not a string and has no string `field`.
+    //# UNIT TESTED
+    pub fn parse(&self, output: &str) -> Result<HashSet<String>, Error> {
+        let packages = match self {
+            ListParser::Whitespace => output.split_whitespace().map(String::from).collect(),
+            ListParser::Lines => output.lines().map(str::trim).filter(|line| !line.is_empty()).map(String::from).collect(),
+            ListParser::Regex { regex, group } => output
+                .lines()
+                .filter_map(|line| regex.captures(line)?.get(*group))
+                .map(|package| package.as_str().to_string())
+                .collect(),
+            ListParser::Column {
+                column,
+                delimiter,
+                skip_lines,
+            } => output
+                .lines()
+                .skip(*skip_lines)
+                .filter_map(|line| match delimiter {
+                    Some(delimiter) => line.split(delimiter.as_str()).nth(*column),
+                    None => line.split_whitespace().nth(*column),
+                })
+                .map(str::trim)
+                .filter(|package| !package.is_empty())
+                .map(String::from)
+                .collect(),
+            ListParser::Json { pointer, field } => return parse_json(output, pointer, field.as_deref()),
+        };
+        Ok(packages)
+    }
+}
+impl TryFrom<ListParserProxy> for ListParser {
+    type Error = String;
+
+    fn try_from(proxy: ListParserProxy) -> Result<Self, Self::Error> {
+        Ok(match proxy {
+            ListParserProxy::Whitespace => ListParser::Whitespace,
+            ListParserProxy::Lines => ListParser::Lines,
+            ListParserProxy::Regex { pattern, group } => {
+                let regex = Regex::new(&pattern).map_err(|e| format!("Invalid list parser pattern: {}", e))?;
+                if group >= regex.captures_len() {
+                    return Err(format!("List parser pattern `{}` has no capture group {}", pattern, group));
+                }
+                ListParser::Regex { regex, group }
+            }
+            ListParserProxy::Column {
+                column,
+                delimiter,
+                skip_lines,
+            } => {
+                if delimiter.as_deref() == Some("") {
+                    return Err("List parser delimiter can't be empty".to_string());
+                }
+                ListParser::Column {
+                    column,
+                    delimiter,
+                    skip_lines,
+                }
+            }
+            ListParserProxy::Json { pointer, field } => ListParser::Json { pointer, field },
+        })
+    }
+}
+
+/// Helper function to read package names from JSON `output`.
+fn parse_json(output: &str, pointer: &str, field: Option<&str>) -> Result<HashSet<String>, Error> {
+    let json = serde_json::from_str::<Value>(output).map_err(|e| Error::command(format!("List command returned invalid JSON: {}", e)))?;
+    let Some(value) = json.pointer(pointer) else {
+        return Err(Error::command(format!("List command output has nothing at `{}`", pointer)));
+    };
+    match value {
+        Value::Object(object) => Ok(object.keys().cloned().collect()),
+        Value::Array(items) => items
+            .iter()
+            .map(|item| {
+                let package = match field {
+                    Some(field) => item.get(field),
+                    None => Some(item),
+                };
+                package
+                    .and_then(Value::as_str)
+                    .map(String::from)
+                    .ok_or_else(|| Error::command(format!("List command output has a non-string package: {}", item)))
+            })
+            .collect(),
+        _ => Err(Error::command(format!("List command output at `{}` is not an array or object", pointer))),
+    }
+}
@@ -0,0 +1,116 @@
+use super::*;
+
+/// Parse the `list_parser` table of the package manager file `contents`.
+fn list_parser_in(contents: &str) -> ListParser {
+    #[derive(Deserialize)]
+    struct Wrapper {
+        list_parser: ListParser,
+    }
+    toml::from_str::<Wrapper>(contents).unwrap().list_parser
+}
+
+/// Parse a `list_parser` table.
+fn list_parser(table: &str) -> ListParser {
+    list_parser_in(&format!("[list_parser]\n{}", table))
+}
+
+/// Get the `list_parser` table of the preset `name`.
+fn preset_list_parser(name: &str) -> ListParser {
+    list_parser_in(crate::pac::presets::preset(name).unwrap())
+}
+
+/// Build a `HashSet` of package names.
+fn packages(names: &[&str]) -> HashSet<String> {
+    names.iter().map(|name| name.to_string()).collect()
+}
+
+/// Tests the `whitespace` mode.
+#[test]
+fn whitespace() {
+    assert_eq!(ListParser::default().parse("nano\ntrash-cli  vim\n").unwrap(), packages(&["nano", "trash-cli", "vim"]));
+}
+
+/// Tests the `lines` mode.
+#[test]
+fn lines() {
+    let parser = list_parser("mode = \"lines\"");
+    assert_eq!(parser.parse("  org.gimp.GIMP \n\nfirst second\n").unwrap(), packages(&["org.gimp.GIMP", "first second"]));
+}
+
+/// Tests the `regex` mode against `apt list --installed`.
+#[test]
+fn regex_apt() {
+    let parser = list_parser("mode = \"regex\"\npattern = '^([^/]+)/'");
+    assert_eq!(parser.parse(include_str!("./samples/apt.txt")).unwrap(), packages(&["bash", "libc6", "vim"]));
+}
+
+/// Tests the `regex` mode against `dnf list installed`.
+#[test]
+fn regex_dnf() {
+    let parser = list_parser("mode = \"regex\"\npattern = '^(\\S+)\\.[^.\\s]+\\s+\\S+\\s+@'");
+    assert_eq!(parser.parse(include_str!("./samples/dnf.txt")).unwrap(), packages(&["bash", "python3-libs", "tzdata"]));
+}
+
+/// Tests the `cargo` preset against `cargo install --list`, including crates
+/// installed from git and a path.
+#[test]
+fn regex_cargo() {
+    let parser = preset_list_parser("cargo");
+    assert_eq!(parser.parse(include_str!("./samples/cargo.txt")).unwrap(), packages(&["cargo-edit", "just", "mytool", "ripgrep"]));
+}
+
+/// Tests the `zypper` preset, which skips packages installed as dependencies.
+#[test]
+fn regex_zypper() {
+    let parser = preset_list_parser("zypper");
+    assert_eq!(parser.parse(include_str!("./samples/zypper.txt")).unwrap(), packages(&["htop", "vim"]));
+}
+
+/// Tests the `column` mode against `pip list`.
+#[test]
+fn column_pip() {
+    let parser = list_parser("mode = \"column\"\ncolumn = 0\nskip_lines = 2");
+    assert_eq!(parser.parse(include_str!("./samples/pip.txt")).unwrap(), packages(&["pip", "requests", "setuptools"]));
+}
+
+/// Tests the `column` mode against `flatpak list`.
+#[test]
+fn column_flatpak() {
+    let parser = list_parser("mode = \"column\"\ncolumn = 1\ndelimiter = \"\\t\"");
+    assert_eq!(parser.parse(include_str!("./samples/flatpak.txt")).unwrap(), packages(&["org.mozilla.firefox", "org.gimp.GIMP"]));
+}
+
+/// Tests the `json` mode against `npm ls --global --json`.
+#[test]
+fn json_object() {
+    let parser = list_parser("mode = \"json\"\npointer = \"/dependencies\"");
+    assert_eq!(parser.parse(include_str!("./samples/npm.json")).unwrap(), packages(&["npm", "typescript"]));
+}
+
+/// Tests the `json` mode against `pip list --format=json`.
+#[test]
+fn json_array() {
+    let parser = list_parser("mode = \"json\"\npointer = \"\"\nfield = \"name\"");
+    assert_eq!(parser.parse(include_str!("./samples/pip.json")).unwrap(), packages(&["pip", "requests"]));
+    // a missing field is an error
+    let parser = list_parser("mode = \"json\"\npointer = \"\"\nfield = \"missing\"");
+    assert!(parser.parse(include_str!("./samples/pip.json")).is_err());
+}
+
+/// Test the tables that fail to parse.
+#[test]
+fn invalid() {
+    #[derive(Deserialize, Debug)]
+    #[allow(dead_code)]
+    struct Wrapper {
+        list_parser: ListParser,
+    }
+    for table in [
+        "mode = \"regex\"\npattern = '('",
+        "mode = \"regex\"\npattern = '^\\S+$'",
+        "mode = \"column\"\ncolumn = 0\ndelimiter = \"\"",
+        "mode = \"csv\"",
+    ] {
+        assert!(toml::from_str::<Wrapper>(&format!("[list_parser]\n{}", table)).is_err(), "{}", table);
+    }
+}
@@ -0,0 +1,199 @@
+//! A package file that keeps its comments, sections and annotations.
+use std::collections::HashSet;
+use std::fmt;
+use std::str::FromStr;
+
+use itertools::Itertools;
+
+use super::exclusion::REGEX_PREFIX;
+use crate::Error;
+
+#[cfg(test)]
+mod tests;
+
+/// A line in a package file.
+#[derive(Debug, Clone, PartialEq)]
+enum Line {
+    Blank,
+    /// A line starting with `#`, stored without the `#`.
+    Comment(String),
+    /// A `[name]` line that starts a named section.
+    Section(String),
+    /// A package, optionally followed by `# annotation`.
+    Package {
+        name: String,
+        annotation: Option<String>,
+    },
+}
+
+/// The contents of a package file.
+///
+/// # Format
+/// - Each line is a package name, optionally followed by `# annotation`, such
+///   as why the package was installed.
+/// - Lines starting with `#` are comments.
+/// - A line starting with `re:` is a regex in the excluded packages file. The
+///   whole line is kept, including any `#` or whitespace, so it can't have an
+///   annotation.
+/// - A `[name]` line starts a named section.
+/// - Blank lines are kept.
+///
+/// ## Format Example
+/// <pre>
+/// # Installed on every machine
+/// git
+/// nano # the editor for commit messages
+///
+/// [fonts]
+/// noto-fonts
+/// </pre>
+#[derive(Debug, Default, Clone, PartialEq)]
+pub struct PackageList {
+    lines: Vec<Line>,
+}
+impl PackageList {
+    /// Get the names of every package.
+    pub fn packages(&self) -> HashSet<String> {
+        self.package_names().map(String::from).collect()
+    }
+
+    /// Iterate over the package names in file order.
+    pub fn package_names(&self) -> impl Iterator<Item = &str> {
+        self.lines.iter().filter_map(|line| match line {
+            Line::Package { name, .. } => Some(name.as_str()),
+            _ => None,
+        })
+    }
+
+    /// Whether the list has no packages.
+    pub fn is_empty(&self) -> bool {
+        self.package_names().next().is_none()
+    }
+
+    /// Whether `package` is in the list.
+    pub fn contains(&self, package: &str) -> bool {
+        self.package_names().any(|name| name == package)
+    }
+
+    /// Get the packages listed more than once, in sorted order.
+    //# UNIT TESTED
+    pub fn duplicates(&self) -> Vec<&str> {
+        self.package_names().duplicates().sorted().collect()
+    }
+
+    /// Get the annotation of `package`, if it has one.
+    pub fn annotation(&self, package: &str) -> Option<&str> {
+        self.lines.iter().find_map(|line| match line {
+            Line::Package { name, annotation } if name == package => annotation.as_deref(),
+            _ => None,
+        })
+    }
+
+    /// Add `package` to the first section in sorted order. Returns whether it was added.
+    //# UNIT TESTED
+    pub fn insert(&mut self, package: &str) -> bool {
+        if self.contains(package) {
+            return false;
+        }
+        let line = Line::Package {
+            name: package.to_string(),
+            annotation: None,
+        };
+        // only consider the lines before the first named section
+        let first_section_end = self
+            .lines
+            .iter()
+            .position(|line| matches!(line, Line::Section(_)))
+            .unwrap_or(self.lines.len());
+        let first_section = &self.lines[..first_section_end];
+        let position = first_section
+            .iter()
+            .position(|line| matches!(line, Line::Package { name, .. } if name.as_str() > package))
+            .or_else(|| {
+                first_section
+                    .iter()
+                    .rposition(|line| matches!(line, Line::Package { .. }))
+                    .map(|index| index + 1)
+            })
+            .unwrap_or(first_section_end);
+        self.lines.insert(position, line);
+        true
+    }
+
+    /// Remove `package`. Returns whether it was removed.
+    //# UNIT TESTED
+    pub fn remove(&mut self, package: &str) -> bool {
+        let length = self.lines.len();
+        self.lines.retain(|line| !matches!(line, Line::Package { name, .. } if name == package));
+        self.lines.len() != length
+    }
+
+    /// Make the list contain exactly `packages`, keeping the comments and
+    /// annotations of the packages that stay. New packages are inserted in
+    /// sorted order.
+    //# UNIT TESTED
+    pub fn set_packages(&mut self, packages: &HashSet<String>) {
+        self.lines.retain(|line| match line {
+            Line::Package { name, .. } => packages.contains(name),
+            _ => true,
+        });
+        let mut new_packages = packages.iter().filter(|package| !self.contains(package)).cloned().collect::<Vec<_>>();
+        new_packages.sort();
+        for package in new_packages {
+            self.insert(&package);
+        }
+    }
+}
+impl FromStr for PackageList {
+    type Err = Error;
+
+    fn from_str(contents: &str) -> Result<Self, Self::Err> {
+        let mut lines = Vec::new();
+        for (number, line) in contents.lines().enumerate() {
+            let line = line.trim();
+            lines.push(if line.is_empty() {
+                Line::Blank
+            } else if let Some(comment) = line.strip_prefix('#') {
+                Line::Comment(comment.to_string())
+            } else if let Some(section) = line.strip_prefix('[').and_then(|line| line.strip_suffix(']')) {
+                Line::Section(section.trim().to_string())
+            } else if line.starts_with(REGEX_PREFIX) {
+                // `#` and whitespace are part of the regex
+                Line::Package {
+                    name: line.to_string(),
+                    annotation: None,
+                }
+            } else {
+                let (name, annotation) = match line.split_once('#') {
+                    Some((name, annotation)) => (name.trim(), Some(annotation.trim().to_string())),
+                    None => (line, None),
+                };
+                if name.contains(char::is_whitespace) {
+                    return Err(Error::config(format!("Line {} has more than one package: `{}`", number + 1, name)));
+                }
+                Line::Package {
+                    name: name.to_string(),
+                    annotation,
+                }
+            });
+        }
+        Ok(PackageList { lines })
+    }
+}
+impl fmt::Display for PackageList {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        for line in &self.lines {
+            match line {
+                Line::Blank => writeln!(f)?,
+                Line::Comment(comment) => writeln!(f, "#{}", comment)?,
+                Line::Section(section) => writeln!(f, "[{}]", section)?,
+                Line::Package { name, annotation: None } => writeln!(f, "{}", name)?,
+                Line::Package {
+                    name,
+                    annotation: Some(annotation),
+                } => writeln!(f, "{} # {}", name, annotation)?,
+            }
+        }
+        Ok(())
+    }
+}
@@ -0,0 +1,71 @@
+use super::*;
+
+const CONTENTS: &str = "# Installed on every machine\ngit\nnano # for commit messages\nvim\n\n[fonts]\nnoto-fonts\n";
+
+/// Parse `CONTENTS`.
+fn package_list() -> PackageList {
+    CONTENTS.parse().unwrap()
+}
+
+/// Tests parsing and writing a package file.
+#[test]
+fn round_trip() {
+    let package_list = package_list();
+    assert_eq!(package_list.packages(), ["git", "nano", "vim", "noto-fonts"].map(String::from).into());
+    assert_eq!(package_list.annotation("nano"), Some("for commit messages"));
+    assert_eq!(package_list.to_string(), CONTENTS);
+}
+
+/// Tests parsing an invalid package file.
+#[test]
+fn invalid() {
+    assert!("git nano\n".parse::<PackageList>().is_err());
+}
+
+/// Regex lines are kept whole, even with `#` and whitespace.
+#[test]
+fn regex() {
+    let contents = "re:lib#\\d+\nre:a b # c\n";
+    let package_list = contents.parse::<PackageList>().unwrap();
+    assert_eq!(package_list.packages(), ["re:lib#\\d+", "re:a b # c"].map(String::from).into());
+    assert_eq!(package_list.to_string(), contents);
+}
+
+/// Tests `duplicates()`.
+#[test]
+fn duplicates() {
+    assert!(package_list().duplicates().is_empty());
+    let package_list = "vim\ngit\nvim\n\n[work]\ngit\nvim\n".parse::<PackageList>().unwrap();
+    assert_eq!(package_list.duplicates(), ["git", "vim"]);
+}
+
+/// Tests `insert()`.
+#[test]
+fn insert() {
+    let mut package_list = package_list();
+    assert!(package_list.insert("htop"));
+    assert!(package_list.insert("zsh"));
+    assert!(!package_list.insert("git"));
+    assert_eq!(package_list.to_string(), "# Installed on every machine\ngit\nhtop\nnano # for commit messages\nvim\nzsh\n\n[fonts]\nnoto-fonts\n");
+    // an empty list just gets the package
+    let mut package_list = PackageList::default();
+    package_list.insert("git");
+    assert_eq!(package_list.to_string(), "git\n");
+}
+
+/// Tests `remove()`.
+#[test]
+fn remove() {
+    let mut package_list = package_list();
+    assert!(package_list.remove("nano"));
+    assert!(!package_list.remove("nano"));
+    assert_eq!(package_list.to_string(), "# Installed on every machine\ngit\nvim\n\n[fonts]\nnoto-fonts\n");
+}
+
+/// Tests `set_packages()`.
+#[test]
+fn set_packages() {
+    let mut package_list = package_list();
+    package_list.set_packages(&["nano", "noto-fonts", "curl", "zsh"].map(String::from).into());
+    assert_eq!(package_list.to_string(), "# Installed on every machine\ncurl\nnano # for commit messages\nzsh\n\n[fonts]\nnoto-fonts\n");
+}
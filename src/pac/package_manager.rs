@@ -2,152 +2,812 @@
 mod tests;
 
 use std::collections::HashSet;
-use std::ffi::OsStr;
+use std::ffi::{OsStr, OsString};
+use std::fmt;
 use std::fs;
+use std::io::{self, Write};
 use std::path::Path;
-use std::process::Command;
+use std::process::{Command, Output, Stdio};
+use std::thread;
 
+use itertools::Itertools;
 use toml;
 
+#[cfg(test)]
+use super::backend::MemoryBackend;
+use super::backend::{BackendType, PackageBackend};
+use super::list_parser::ListParser;
+use super::privilege::Privilege;
+use super::template::TemplateContext;
+use crate::Error;
+
+/// An install argument that is replaced by every package.
+pub const PACKAGES_PLACEHOLDER: &str = "{packages}";
+/// Text in an install argument that is replaced by a single package, running
+/// the install command once per package.
+pub const PACKAGE_PLACEHOLDER: &str = "{package}";
+
+/// The shell that runs `shell` commands.
+const SHELL: &str = "sh";
+
+/// Programs that run another program with elevated privileges.
+const PRIVILEGE_WRAPPERS: [&str; 3] = ["sudo", "doas", "run0"];
+
 mod toml_structs {
     //! Structs used to parse **.toml** files with the `toml` crate.
+    use std::collections::BTreeMap;
+    use std::path::PathBuf;
+
     use serde_derive::Deserialize;
+
+    use super::{BackendType, ListParser};
+
+    /// Where a command's stdin comes from.
+    #[derive(Deserialize, Default, PartialEq)]
+    #[serde(rename_all = "lowercase")]
+    pub enum StdinProxy {
+        /// Inherit stdin from `dotfile`.
+        #[default]
+        Inherit,
+        /// Write the packages to stdin, one per line, instead of passing them as arguments.
+        Packages,
+    }
+
     /// A proxy for a `Command`.
     #[derive(Deserialize)]
     pub struct CommandProxy {
-        pub command: String,
+        #[serde(default)]
+        pub command: Option<String>,
+        #[serde(default)]
         pub args: Vec<String>,
+        #[serde(default)]
+        pub shell: Option<String>,
+        #[serde(default)]
+        pub env: BTreeMap<String, String>,
+        #[serde(default)]
+        pub cwd: Option<PathBuf>,
+        #[serde(default)]
+        pub stdin: StdinProxy,
+        #[serde(default)]
+        pub per_package: bool,
+        #[serde(default)]
+        pub root: bool,
     }
 
-    /// A proxy for a `PackageManager`.
+    /// The backend type of a package manager file.
+    #[derive(Deserialize)]
+    pub struct BackendTypeProxy {
+        #[serde(rename = "type", default)]
+        pub backend_type: BackendType,
+    }
+
+    /// A proxy for a `CommandBackend`.
     #[derive(Deserialize)]
     pub struct PackageManagerProxy {
         pub install_command: CommandProxy,
         pub list_command: CommandProxy,
+        #[serde(default)]
+        pub check_command: Option<CommandProxy>,
+        #[serde(default)]
+        pub bootstrap_command: Option<CommandProxy>,
+        #[serde(default)]
+        pub list_parser: ListParser,
     }
 }
 use toml_structs::*;
 
 /// Represents a system's package manager with methods to list the installed
-/// packages and install new ones.
+/// packages and install new ones. The work is done by a `PackageBackend`,
+/// which is a `CommandBackend` unless the package manager file says otherwise.
 pub struct PackageManager {
-    pub install_command: Command,
-    pub list_command: Command,
+    backend: Box<dyn PackageBackend>,
 }
 impl PackageManager {
-    /// Build a `PackageManager`.
+    /// Build a `PackageManager` with a `CommandBackend`.
     ///
     /// # Parameters
     /// - `install_command` - The command used to install packages.
     /// - `list_command` - The command used to list installed packages.
     pub fn build(install_command: Command, list_command: Command) -> Self {
-        PackageManager {
-            install_command,
-            list_command,
-        }
+        Self::with_backend(CommandBackend::build(install_command, list_command))
     }
 
-    /// Build from a TOML file.
+    /// Build a `PackageManager` that uses `backend`.
+    pub fn with_backend(backend: impl PackageBackend + 'static) -> Self {
+        PackageManager { backend: Box::new(backend) }
+    }
+
+    /// Build from a TOML file. The file is rendered as a template describing
+    /// the current machine first (see `TemplateContext`).
     ///
     /// # Errors
     /// - The file cannot be read from.
+    /// - The file is an invalid template.
     /// - The file's format is invalid.
     ///
     /// # File Format
-    /// - `name` - the name of the package manager.
+    /// - `type` - The backend to use, `command` by default (see
+    ///   `BackendType`). The rest of the format is for `command`.
     /// - A table called `install_command` with parameters:
     ///     - `command` - The command to run.
     ///     - `args` - An array of arguments to pass to the command.
+    ///     - `shell` - Instead of `command` and `args`, a shell command line to
+    ///       run with `sh -c`. The packages are passed as positional parameters
+    ///       (`"$@"`), or quoted in place of a `{packages}`/`{package}` placeholder.
+    ///     - `env` - An optional table of environment variables to set.
+    ///     - `cwd` - An optional directory to run the command in. A relative
+    ///       path is relative to the folder of the package manager file.
+    ///     - `stdin` - Set to `"packages"` to write the packages to stdin
+    ///       instead of passing them as arguments. Only valid for
+    ///       `install_command`.
+    ///     - `per_package` - Set to `true` to run the command once per package.
+    ///       Only valid for `install_command`.
+    ///     - `root` - Set to `true` to run the command as root (see `Privilege`).
+    ///
+    ///   The packages are appended to `args` unless an argument is `{packages}`,
+    ///   which is replaced by all of the packages, or contains `{package}`,
+    ///   which runs the command once per package. `{packages}` must be a whole
+    ///   argument, like `"{packages}"` but not `"--pkgs={packages}"`.
     /// - A table called `list_command` with the same parameters as `install_command`.
+    /// - An optional table called `list_parser` describing the list command's
+    ///   output format (see `ListParser`).
+    /// - An optional table called `check_command` that succeeds if the package
+    ///   manager is present.
+    /// - An optional table called `bootstrap_command` that installs the package
+    ///   manager if it is missing.
     ///
     /// ## Format Example
     /// <pre>
-    /// name = "pacman"
-    ///
     /// [install_command]
     /// command = "sudo"
-    /// args = ["pacman", "-S", "--needed"]
+    /// args = ["pacman", "-S", "--needed", "-"]
+    /// stdin = "packages"
     ///
     /// [list_command]
     /// command = "pacman"
     /// args = ["-Qqen"]
+    ///
+    /// [check_command]
+    /// command = "pacman"
+    /// args = ["--version"]
     /// </pre>
     //# HELPERS UNIT TESTED
-    pub fn from_toml(path: impl AsRef<Path>) -> Result<Self, String> {
+    pub fn from_toml(path: impl AsRef<Path>) -> Result<Self, Error> {
+        let path = path.as_ref();
         // try to read the contents of the file
-        let Ok(contents) = fs::read_to_string(&path) else {
-            return Err(format!("Failed to read package manager file at {}", &path.as_ref().to_string_lossy()));
-        };
-        Self::from_toml_string(contents)
+        let template = fs::read_to_string(path).map_err(|e| Error::io("read", path, e))?;
+        TemplateContext::detect()
+            .render(&template)
+            .and_then(|contents| Self::from_toml_string(contents, path.parent().unwrap_or(Path::new(""))))
+            .map_err(|e| e.in_file(path))
     }
 
-    /// Helper function to build from a TOML string.
+    /// Helper function to build from a TOML string, with the backend chosen by
+    /// its `type`. Relative paths are relative to `folder`. Errors aren't tied
+    /// to a file; use `Error::in_file()` to add one.
     //# UNIT TESTED
-    fn from_toml_string(toml_string: impl AsRef<str>) -> Result<Self, String> {
+    pub(crate) fn from_toml_string(toml_string: impl AsRef<str>, folder: impl AsRef<Path>) -> Result<Self, Error> {
         let toml_string = toml_string.as_ref();
-        // try to convert to a `PackageManagerProxy`
-        let Ok(proxy) = toml::from_str::<PackageManagerProxy>(&toml_string) else {
-            // return the invalid file contents on failure
-            return Err(format!("Invalid package manager file contents:\n{}", toml_string.to_string()));
+        Ok(match toml::from_str::<BackendTypeProxy>(toml_string)?.backend_type {
+            BackendType::Command => Self::with_backend(CommandBackend::from_toml_string(toml_string, folder)?),
+            #[cfg(test)]
+            BackendType::Memory => Self::with_backend(toml::from_str::<MemoryBackend>(toml_string)?),
+        })
+    }
+
+    /// Run the commands that need root with `privilege`. Does nothing unless
+    /// the backend runs commands.
+    pub fn set_privilege(&mut self, privilege: Privilege) {
+        self.backend.set_privilege(privilege);
+    }
+
+    /// Install the package manager if it is missing.
+    ///
+    /// # Errors
+    /// - Any errors from `PackageBackend::bootstrap()`.
+    //# UNIT TESTED
+    pub fn bootstrap(&mut self) -> Result<&mut Self, Error> {
+        self.backend.bootstrap()?;
+        Ok(self)
+    }
+
+    /// Attempt to install the provided `packages` that aren't installed yet,
+    /// bootstrapping the package manager first if needed.
+    ///
+    /// # Errors
+    /// - Any errors from `bootstrap()`.
+    /// - Any errors from `PackageBackend::list()`.
+    /// - Any errors from `PackageBackend::install()`.
+    //# INTEGRATION TESTED
+    pub fn install(&mut self, packages: impl IntoIterator<Item = impl AsRef<OsStr>>) -> Result<InstallReport, Error> {
+        // the package manager must be present to list what is installed
+        self.bootstrap()?;
+        let installed_packages = self.backend.list()?;
+        let (already_installed, missing_packages): (Vec<_>, Vec<_>) =
+            to_strings(packages).into_iter().partition(|package| installed_packages.contains(package));
+        let mut report = if missing_packages.is_empty() {
+            InstallReport::default()
+        } else {
+            self.backend.install(&missing_packages)?
         };
-        Ok(proxy.into())
+        report.already_installed = already_installed;
+        Ok(report)
     }
 
-    /// Attempt to install the provided `packages`.
+    /// Attempt to list the package manager's installed packages.
     ///
     /// # Errors
-    /// - The install command fails to run.
-    /// - The install command runs, but returns an error code.
+    /// - Any errors from `PackageBackend::list()`.
     //# INTEGRATION TESTED
-    pub fn install(&mut self, packages: impl IntoIterator<Item = impl AsRef<OsStr>>) -> Result<&mut Self, String> {
-        self.install_command.args(packages);
-        if let Ok(status) = self.install_command.status() {
-            if !status.success() {
-                return Err("Install command failed".to_string());
+    pub fn list(&mut self) -> Result<HashSet<String>, Error> {
+        self.backend.list()
+    }
+
+    /// Whether the package manager can be used on this machine.
+    pub fn is_available(&self) -> bool {
+        self.backend.is_available()
+    }
+
+    /// Get the programs this package manager runs, if it runs commands.
+    pub fn programs(&self) -> Vec<&OsStr> {
+        self.backend.programs()
+    }
+
+    /// Whether any of this package manager's commands run as root.
+    pub fn needs_root(&self) -> bool {
+        self.backend.needs_root()
+    }
+
+    /// Get how commands that need root are run.
+    pub fn privilege(&self) -> Privilege {
+        self.backend.privilege()
+    }
+
+    /// Describe how the package manager runs its commands, such as the ones
+    /// that run through the shell or as root.
+    pub fn notes(&self) -> Vec<String> {
+        self.backend.notes()
+    }
+}
+
+/// A backend that runs the commands in a package manager file to list the
+/// installed packages and install new ones.
+pub struct CommandBackend {
+    pub install_command: Command,
+    pub list_command: Command,
+    /// Succeeds if the package manager is present on this machine.
+    pub check_command: Option<Command>,
+    /// Installs the package manager when `check_command` fails.
+    pub bootstrap_command: Option<Command>,
+    /// Write the packages to the install command's stdin instead of passing
+    /// them as arguments.
+    pub packages_on_stdin: bool,
+    /// Run the install command once per package.
+    pub per_package: bool,
+    /// Reads package names from the list command's output.
+    pub list_parser: ListParser,
+    /// The names of the tables whose commands run through the shell.
+    pub shell_tables: Vec<&'static str>,
+    /// The names of the tables whose commands run as root.
+    pub root_tables: Vec<&'static str>,
+    /// How to run the commands in `root_tables` as root.
+    pub privilege: Privilege,
+}
+impl CommandBackend {
+    /// Build a `CommandBackend`.
+    ///
+    /// # Parameters
+    /// - `install_command` - The command used to install packages.
+    /// - `list_command` - The command used to list installed packages.
+    pub fn build(install_command: Command, list_command: Command) -> Self {
+        CommandBackend {
+            install_command,
+            list_command,
+            check_command: None,
+            bootstrap_command: None,
+            packages_on_stdin: false,
+            per_package: false,
+            list_parser: ListParser::default(),
+            shell_tables: Vec::new(),
+            root_tables: Vec::new(),
+            privilege: Privilege::default(),
+        }
+    }
+
+    /// Helper function to build from a rendered package manager file, with
+    /// relative paths relative to `folder`. Errors aren't tied to a file; use
+    /// `Error::in_file()` to add one.
+    //# UNIT TESTED
+    pub(crate) fn from_toml_string(toml_string: impl AsRef<str>, folder: impl AsRef<Path>) -> Result<Self, Error> {
+        let mut proxy = toml::from_str::<PackageManagerProxy>(toml_string.as_ref())?;
+        proxy.resolve_paths(folder.as_ref());
+        proxy.validate().map_err(Error::config)?;
+        Ok(proxy.into())
+    }
+
+    /// Whether the package manager is present on this machine. Uses the check
+    /// command if there is one, otherwise checks the `PATH` for its programs.
+    //# UNIT TESTED
+    pub fn is_present(&mut self) -> bool {
+        match &self.check_command {
+            Some(check_command) => self
+                .prepare("check_command", check_command, check_command.get_args())
+                .output()
+                .is_ok_and(|output| output.status.success()),
+            None => self.is_available(),
+        }
+    }
+
+    /// Helper function to install `packages`, bisecting on failure and
+    /// recording the results in `report`.
+    //# UNIT TESTED
+    fn install_isolated(&self, packages: &[String], report: &mut InstallReport) -> Result<(), Error> {
+        if packages.is_empty() {
+            return Ok(());
+        }
+        let output = self.run_install_command(packages).map_err(|e| Error::Command {
+            message: String::from("Could not run install command"),
+            source: Some(e),
+        })?;
+        // still show the user what went wrong
+        eprint!("{}", String::from_utf8_lossy(&output.stderr));
+        if output.status.success() {
+            report.installed.extend_from_slice(packages);
+        } else if let [package] = packages {
+            report.failed.push(PackageFailure {
+                package: package.to_owned(),
+                exit_code: output.status.code(),
+                stderr: String::from_utf8_lossy(&output.stderr).trim().to_string(),
+            });
+        } else {
+            println!("Install command failed for {} packages, retrying in smaller batches", packages.len());
+            let (first, second) = packages.split_at(packages.len() / 2);
+            self.install_isolated(first, report)?;
+            self.install_isolated(second, report)?;
+        }
+        Ok(())
+    }
+
+    /// Get the install command's arguments for `packages`. A `{packages}`
+    /// argument is replaced by all of the packages and `{package}` is replaced
+    /// by the first one. In a shell command line, the placeholders are replaced
+    /// by quoted packages. Without a placeholder, the packages are appended
+    /// unless they are written to stdin.
+    //# UNIT TESTED
+    fn install_args(&self, packages: &[String]) -> Vec<OsString> {
+        let in_shell = self.shell_tables.contains(&"install_command");
+        let first = packages.first().map_or("", String::as_str);
+        let mut args = Vec::new();
+        let mut has_placeholder = false;
+        for arg in self.install_command.get_args() {
+            if arg == PACKAGES_PLACEHOLDER && !in_shell {
+                has_placeholder = true;
+                args.extend(packages.iter().map(OsString::from));
+            } else if let Some(arg) = arg.to_str()
+                && (arg.contains(PACKAGE_PLACEHOLDER) || in_shell && arg.contains(PACKAGES_PLACEHOLDER))
+            {
+                has_placeholder = true;
+                let arg = if in_shell {
+                    arg.replace(PACKAGES_PLACEHOLDER, &packages.iter().map(|package| shell_quote(package)).join(" "))
+                        .replace(PACKAGE_PLACEHOLDER, &shell_quote(first))
+                } else {
+                    arg.replace(PACKAGE_PLACEHOLDER, first)
+                };
+                args.push(arg.into());
+            } else {
+                args.push(arg.to_owned());
             }
+        }
+        if !has_placeholder && !self.packages_on_stdin {
+            args.extend(packages.iter().map(OsString::from));
+        }
+        args
+    }
+
+    /// Whether the install command must run once per package, either because
+    /// `per_package` is set or an argument contains `{package}`.
+    pub fn is_per_package(&self) -> bool {
+        self.per_package
+            || self
+                .install_command
+                .get_args()
+                .any(|arg| arg.to_string_lossy().contains(PACKAGE_PLACEHOLDER))
+    }
+
+    /// Helper function to run the install command once for `packages`,
+    /// capturing stderr.
+    fn run_install_command(&self, packages: &[String]) -> io::Result<Output> {
+        let mut install_command = self.prepare("install_command", &self.install_command, self.install_args(packages));
+        install_command.stdout(Stdio::inherit()).stderr(Stdio::piped());
+        if !self.packages_on_stdin {
+            return install_command.output();
+        }
+        let mut child = install_command.stdin(Stdio::piped()).spawn()?;
+        let mut stdin = child.stdin.take().unwrap();
+        let input = packages.iter().map(|package| format!("{}\n", package)).collect::<String>();
+        // write from another thread while stderr is read, so neither pipe fills
+        // up and blocks both processes. Stdin is closed when the thread ends, so
+        // the command stops reading
+        let writer = thread::spawn(move || stdin.write_all(input.as_bytes()));
+        let output = child.wait_with_output()?;
+        match writer.join().expect("writing to stdin doesn't panic") {
+            // the command exited without reading everything, which its exit status reports
+            Err(e) if e.kind() != io::ErrorKind::BrokenPipe => Err(e),
+            _ => Ok(output),
+        }
+    }
+
+    /// Helper function to copy the command from `table` with `args`, running it
+    /// as root if it needs to be.
+    fn prepare(&self, table: &str, command: &Command, args: impl IntoIterator<Item = impl AsRef<OsStr>>) -> Command {
+        let command = copy_command(command, args);
+        if self.root_tables.contains(&table) {
+            self.privilege.wrap(command)
         } else {
-            return Err("Could not run install command".to_string());
+            command
         }
-        Ok(self)
     }
+}
 
+impl PackageBackend for CommandBackend {
     /// Attempt to list the package manager's installed packages. The list
-    /// command must return the installed packages separated by whitespace.
+    /// command's output is read with the `list_parser`, which expects the
+    /// packages to be separated by whitespace by default.
     ///
     /// # Errors
     /// - The list command fails.
-    /// - The list command returns an invalid package format (i.e. not whitespace-separated).
+    /// - The list command returns invalid UTF-8.
+    /// - Any errors from `ListParser::parse()`.
     //# INTEGRATION TESTED
-    pub fn list(&mut self) -> Result<HashSet<String>, String> {
+    fn list(&mut self) -> Result<HashSet<String>, Error> {
         // run the list command and capture the output
-        let Ok(output) = self.list_command.output() else {
-            return Err("Failed to list packages".to_string());
-        };
+        let output = self
+            .prepare("list_command", &self.list_command, self.list_command.get_args())
+            .output()
+            .map_err(|e| Error::Command {
+                message: String::from("Failed to list packages"),
+                source: Some(e),
+            })?;
+        // an empty list from a failed command would look like everything was uninstalled
+        if !output.status.success() {
+            return Err(Error::command(format!("List command failed: {}", String::from_utf8_lossy(&output.stderr).trim_end())));
+        }
         // convert the output to a String
         let Ok(output) = String::from_utf8(output.stdout) else {
-            return Err("List command returned invalid format".to_string());
+            return Err(Error::command("List command returned invalid UTF-8"));
         };
         // convert the output to a list of Strings
-        Ok(output.split_whitespace().map(String::from).collect())
+        self.list_parser.parse(&output)
+    }
+
+    /// Attempt to install the provided `packages`. The packages are installed
+    /// in one batch, or one at a time if `is_per_package()`. If the batch
+    /// fails, it is split in half and retried until the packages that fail on
+    /// their own are found, so everything else still gets installed.
+    ///
+    /// # Errors
+    /// - The install command fails to run.
+    //# UNIT TESTED
+    fn install(&mut self, packages: &[String]) -> Result<InstallReport, Error> {
+        let mut report = InstallReport::default();
+        if self.is_per_package() {
+            for package in packages.chunks(1) {
+                self.install_isolated(package, &mut report)?;
+            }
+        } else {
+            self.install_isolated(packages, &mut report)?;
+        }
+        Ok(report)
+    }
+
+    /// Run the bootstrap command if the package manager is not present. Does
+    /// nothing if there is no bootstrap command.
+    ///
+    /// # Errors
+    /// - The bootstrap command fails to run.
+    /// - The bootstrap command runs, but returns an error code.
+    /// - The package manager is still missing after bootstrapping.
+    //# UNIT TESTED
+    fn bootstrap(&mut self) -> Result<(), Error> {
+        if self.bootstrap_command.is_none() || self.is_present() {
+            return Ok(());
+        }
+        println!("Package manager is missing, bootstrapping");
+        let bootstrap_command = self.bootstrap_command.as_ref().unwrap();
+        let status = self
+            .prepare("bootstrap_command", bootstrap_command, bootstrap_command.get_args())
+            .status()
+            .map_err(|e| Error::Command {
+                message: String::from("Could not run bootstrap command"),
+                source: Some(e),
+            })?;
+        if !status.success() {
+            return Err(Error::command("Bootstrap command failed"));
+        }
+        if !self.is_present() {
+            return Err(Error::command("Package manager is still missing after bootstrapping"));
+        }
+        Ok(())
+    }
+
+    /// Whether every program this package manager runs exists on the `PATH`.
+    //# UNIT TESTED
+    fn is_available(&self) -> bool {
+        self.programs().into_iter().all(|program| utils::find_program(program).is_some())
+    }
+
+    /// Get the programs every command in this package manager runs. When a
+    /// command is wrapped in a privilege escalation program such as `sudo`,
+    /// the wrapped program is included too, and so is the first word of a
+    /// `shell` command line.
+    //# UNIT TESTED
+    fn programs(&self) -> Vec<&OsStr> {
+        let commands = [
+            ("install_command", Some(&self.install_command)),
+            ("list_command", Some(&self.list_command)),
+            ("check_command", self.check_command.as_ref()),
+            ("bootstrap_command", self.bootstrap_command.as_ref()),
+        ];
+        let mut programs = Vec::new();
+        for (table, command) in commands {
+            let Some(command) = command else {
+                continue;
+            };
+            programs.push(command.get_program());
+            let mut args = command.get_args();
+            if self.shell_tables.contains(&table) {
+                // the command line comes after `-c`
+                programs.extend(args.nth(1).and_then(shell_program));
+            } else if PRIVILEGE_WRAPPERS.iter().any(|wrapper| command.get_program() == *wrapper)
+                && let Some(wrapped) = args.next()
+            {
+                programs.push(wrapped);
+            }
+        }
+        programs
+    }
+
+    /// Whether any of this package manager's commands run as root.
+    fn needs_root(&self) -> bool {
+        !self.root_tables.is_empty()
+    }
+
+    fn privilege(&self) -> Privilege {
+        self.privilege
+    }
+
+    fn set_privilege(&mut self, privilege: Privilege) {
+        self.privilege = privilege;
+    }
+
+    /// Name the tables whose commands run through the shell or as root.
+    fn notes(&self) -> Vec<String> {
+        let shell = self.shell_tables.iter().map(|table| format!("`{}` runs through the shell", table));
+        let root = self.root_tables.iter().map(|table| format!("`{}` runs as root", table));
+        shell.chain(root).collect()
     }
 }
 
-impl From<PackageManagerProxy> for PackageManager {
+/// A package that failed to install.
+#[derive(Debug, PartialEq)]
+pub struct PackageFailure {
+    pub package: String,
+    /// The install command's exit code, if it exited normally.
+    pub exit_code: Option<i32>,
+    /// What the install command wrote to stderr.
+    pub stderr: String,
+}
+
+/// The outcome of `PackageManager::install()`.
+#[derive(Debug, Default, PartialEq)]
+pub struct InstallReport {
+    pub installed: Vec<String>,
+    pub failed: Vec<PackageFailure>,
+    /// The packages that were skipped because they were already installed.
+    pub already_installed: Vec<String>,
+}
+impl InstallReport {
+    /// Whether every package was installed.
+    pub fn is_success(&self) -> bool {
+        self.failed.is_empty()
+    }
+}
+impl fmt::Display for InstallReport {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "Installed {} packages, {} failed", self.installed.len(), self.failed.len())?;
+        for failure in &self.failed {
+            let exit_code = failure.exit_code.map_or("none".to_string(), |code| code.to_string());
+            write!(f, "\n  {} (exit code {}): {}", failure.package, exit_code, failure.stderr)?;
+        }
+        Ok(())
+    }
+}
+
+/// Make a new `Command` with the same program, environment and working
+/// directory as `command`, but with `args` as its arguments.
+fn copy_command(command: &Command, args: impl IntoIterator<Item = impl AsRef<OsStr>>) -> Command {
+    let mut copy = Command::new(command.get_program());
+    copy.args(args);
+    for (key, value) in command.get_envs() {
+        match value {
+            Some(value) => copy.env(key, value),
+            None => copy.env_remove(key),
+        };
+    }
+    if let Some(dir) = command.get_current_dir() {
+        copy.current_dir(dir);
+    }
+    copy
+}
+
+/// Helper function to convert `packages` to `String`s.
+fn to_strings(packages: impl IntoIterator<Item = impl AsRef<OsStr>>) -> Vec<String> {
+    packages
+        .into_iter()
+        .map(|package| package.as_ref().to_string_lossy().to_string())
+        .collect()
+}
+
+/// Get the program a shell command line starts with, unless its first word is
+/// shell syntax such as a variable assignment.
+fn shell_program(command_line: &OsStr) -> Option<&OsStr> {
+    command_line
+        .to_str()?
+        .split_whitespace()
+        .next()
+        .filter(|word| word.chars().all(|c| c.is_ascii_alphanumeric() || "-_./+".contains(c)))
+        .map(OsStr::new)
+}
+
+/// Quote `text` so the shell reads it as a single word.
+fn shell_quote(text: &str) -> String {
+    format!("'{}'", text.replace('\'', "'\\''"))
+}
+
+impl CommandProxy {
+    /// Check the command's options.
+    ///
+    /// # Errors
+    /// - Neither or both of `command` and `shell` are set.
+    /// - `args` is set along with `shell`.
+    /// - An environment variable name is empty or contains `=`.
+    /// - `cwd` is not a directory.
+    /// - `stdin = "packages"` or `per_package` is set on a command other than `install_command`.
+    /// - The package placeholders are used in a command other than `install_command`.
+    /// - The package placeholders conflict with each other or with `stdin`/`per_package`.
+    fn validate(&self, table: &str) -> Result<(), String> {
+        match (&self.command, &self.shell) {
+            (None, None) => return Err(format!("`{}` needs either `command` or `shell`", table)),
+            (Some(_), Some(_)) => return Err(format!("`{}` can't have both `command` and `shell`", table)),
+            (None, Some(_)) if !self.args.is_empty() => return Err(format!("`{}` can't have `args` with `shell`", table)),
+            _ => {}
+        }
+        if let Some(key) = self.env.keys().find(|key| key.is_empty() || key.contains('=')) {
+            return Err(format!("Invalid environment variable name `{}` in `{}`", key, table));
+        }
+        if let Some(cwd) = &self.cwd
+            && !cwd.is_dir()
+        {
+            return Err(format!("Working directory `{}` in `{}` is not a directory", cwd.to_string_lossy(), table));
+        }
+        // each package must be its own argument, so there is nothing to substitute into
+        if self
+            .args
+            .iter()
+            .any(|arg| arg != PACKAGES_PLACEHOLDER && arg.contains(PACKAGES_PLACEHOLDER))
+        {
+            return Err(format!(
+                "`{}` must be a whole argument in `{}`, use `{}` to substitute each package inside an argument",
+                PACKAGES_PLACEHOLDER, table, PACKAGE_PLACEHOLDER
+            ));
+        }
+        let (has_packages, has_package) = match &self.shell {
+            Some(shell) => (shell.contains(PACKAGES_PLACEHOLDER), shell.contains(PACKAGE_PLACEHOLDER)),
+            None => (self.args.iter().any(|arg| arg == PACKAGES_PLACEHOLDER), self.args.iter().any(|arg| arg.contains(PACKAGE_PLACEHOLDER))),
+        };
+        if table != "install_command" && (self.stdin == StdinProxy::Packages || self.per_package || has_packages || has_package) {
+            return Err(format!("`stdin`, `per_package` and package placeholders are only valid in `install_command`, not `{}`", table));
+        }
+        if has_packages && (has_package || self.per_package) {
+            return Err(format!("`{}` can't be combined with `{}` or `per_package`", PACKAGES_PLACEHOLDER, PACKAGE_PLACEHOLDER));
+        }
+        if self.stdin == StdinProxy::Packages && (has_packages || has_package) {
+            return Err("Package placeholders can't be combined with `stdin = \"packages\"`".to_string());
+        }
+        Ok(())
+    }
+
+    /// Make a relative `cwd` relative to `folder` instead of the current
+    /// directory.
+    fn resolve_paths(&mut self, folder: &Path) {
+        self.cwd = self.cwd.take().map(|cwd| folder.join(cwd));
+    }
+}
+
+impl PackageManagerProxy {
+    /// Check every command's options.
+    ///
+    /// # Errors
+    /// - Any errors from `CommandProxy::validate()`.
+    fn validate(&self) -> Result<(), String> {
+        self.install_command.validate("install_command")?;
+        self.list_command.validate("list_command")?;
+        if let Some(check_command) = &self.check_command {
+            check_command.validate("check_command")?;
+        }
+        if let Some(bootstrap_command) = &self.bootstrap_command {
+            bootstrap_command.validate("bootstrap_command")?;
+        }
+        Ok(())
+    }
+
+    /// Resolve every command's relative paths against `folder`.
+    fn resolve_paths(&mut self, folder: &Path) {
+        self.install_command.resolve_paths(folder);
+        self.list_command.resolve_paths(folder);
+        for command in [&mut self.check_command, &mut self.bootstrap_command].into_iter().flatten() {
+            command.resolve_paths(folder);
+        }
+    }
+}
+
+impl From<CommandProxy> for Command {
+    fn from(proxy: CommandProxy) -> Self {
+        let mut command = match proxy.shell {
+            // the last argument becomes `$0`, so the packages start at `$1`
+            Some(shell) => {
+                let mut command = Command::new(SHELL);
+                command.args(["-c", &shell, SHELL]);
+                command
+            }
+            None => Command::new(proxy.command.unwrap_or_default()),
+        };
+        command.args(proxy.args).envs(proxy.env);
+        if let Some(cwd) = proxy.cwd {
+            command.current_dir(cwd);
+        }
+        command
+    }
+}
+
+impl From<PackageManagerProxy> for CommandBackend {
     fn from(proxy: PackageManagerProxy) -> Self {
-        let mut package_manager = Self::build(Command::new(proxy.install_command.command), Command::new(proxy.list_command.command));
-        package_manager.install_command.args(proxy.install_command.args);
-        package_manager.list_command.args(proxy.list_command.args);
-        package_manager
+        let tables = [
+            ("install_command", Some(&proxy.install_command)),
+            ("list_command", Some(&proxy.list_command)),
+            ("check_command", proxy.check_command.as_ref()),
+            ("bootstrap_command", proxy.bootstrap_command.as_ref()),
+        ];
+        let tables_where = |condition: fn(&CommandProxy) -> bool| {
+            tables
+                .iter()
+                .filter(|(_, command)| command.is_some_and(condition))
+                .map(|(table, _)| *table)
+                .collect::<Vec<_>>()
+        };
+        let shell_tables = tables_where(|command| command.shell.is_some());
+        let root_tables = tables_where(|command| command.root);
+        let packages_on_stdin = proxy.install_command.stdin == StdinProxy::Packages;
+        let per_package = proxy.install_command.per_package;
+        let mut backend = Self::build(proxy.install_command.into(), proxy.list_command.into());
+        backend.packages_on_stdin = packages_on_stdin;
+        backend.per_package = per_package;
+        backend.list_parser = proxy.list_parser;
+        backend.shell_tables = shell_tables;
+        backend.root_tables = root_tables;
+        backend.check_command = proxy.check_command.map(Command::from);
+        backend.bootstrap_command = proxy.bootstrap_command.map(Command::from);
+        backend
     }
 }
 
-#[cfg(test)]
-impl PartialEq for PackageManager {
+#[cfg(debug_assertions)]
+impl PartialEq for CommandBackend {
     fn eq(&self, other: &Self) -> bool {
         // equal if the commands are equal
         self.install_command.get_args().collect::<Vec<_>>() == other.install_command.get_args().collect::<Vec<_>>()
             && self.list_command.get_args().collect::<Vec<_>>() == other.list_command.get_args().collect::<Vec<_>>()
             && self.install_command.get_program() == other.install_command.get_program()
-            && self.list_command.get_program() == other.install_command.get_program()
+            && self.list_command.get_program() == other.list_command.get_program()
     }
 }
@@ -1,5 +1,10 @@
 use super::*;
 
+/// Helper function to convert `names` to the packages a backend installs.
+fn packages(names: &[&str]) -> Vec<String> {
+    names.iter().map(ToString::to_string).collect()
+}
+
 /// Tests `from_toml_string()`.
 #[cfg(test)]
 mod toml_parsing {
@@ -7,7 +12,7 @@ mod toml_parsing {
     /// Test a valid package manager file.
     #[test]
     fn valid_package_manager() {
-        let package_manager = PackageManager::from_toml_string(include_str!("./files/valid.toml")).unwrap();
+        let package_manager = CommandBackend::from_toml_string(include_str!("./files/valid.toml"), "").unwrap();
         // check install command
         assert_eq!(package_manager.install_command.get_program(), "sudo");
         assert_eq!(package_manager.install_command.get_args().collect::<Vec<_>>(), vec!["pacman", "-S", "--needed", "--noconfirm"]);
@@ -20,6 +25,352 @@ mod toml_parsing {
     #[test]
     #[should_panic]
     fn invalid_package_manager() {
-        PackageManager::from_toml_string(include_str!("./files/invalid.toml")).unwrap();
+        CommandBackend::from_toml_string(include_str!("./files/invalid.toml"), "").unwrap();
+    }
+
+    /// The `type` key picks the backend, and commands are the default.
+    #[test]
+    fn backend_type() {
+        let package_manager = PackageManager::from_toml_string(include_str!("./files/valid.toml"), "").unwrap();
+        assert_eq!(package_manager.programs(), ["sudo", "pacman", "pacman"]);
+        let explicit = PackageManager::from_toml_string(format!("type = \"command\"\n{}", include_str!("./files/valid.toml")), "").unwrap();
+        assert_eq!(explicit.programs(), package_manager.programs());
+
+        let mut memory = PackageManager::from_toml_string("type = \"memory\"\ninstalled = [\"git\"]\n", "").unwrap();
+        assert!(memory.programs().is_empty() && !memory.needs_root() && memory.notes().is_empty());
+        assert!(memory.install(["vim"]).unwrap().is_success());
+        assert_eq!(memory.list().unwrap(), HashSet::from(["git", "vim"].map(String::from)));
+
+        let error = PackageManager::from_toml_string("type = \"native\"\n", "").err().unwrap();
+        assert!(error.to_string().contains("native"), "{}", error);
+    }
+}
+
+/// Tests `programs()`.
+#[cfg(test)]
+mod programs {
+    use super::*;
+
+    /// Privilege escalation wrappers also report the wrapped program.
+    #[test]
+    fn wrapped_program() {
+        let package_manager = CommandBackend::from_toml_string(include_str!("./files/valid.toml"), "").unwrap();
+        assert_eq!(package_manager.programs(), ["sudo", "pacman", "pacman"]);
+    }
+
+    /// The check and bootstrap commands are included too.
+    #[test]
+    fn optional_commands() {
+        let package_manager = CommandBackend::from_toml_string(include_str!("./files/bootstrap.toml"), "").unwrap();
+        assert_eq!(package_manager.programs(), ["yay", "pacman", "yay", "sh"]);
+    }
+
+    /// Shell commands report the program their command line starts with.
+    #[test]
+    fn shell_commands() {
+        let package_manager = CommandBackend::from_toml_string(include_str!("./files/shell.toml"), "").unwrap();
+        assert_eq!(package_manager.programs(), ["sh", "cargo", "sh", "cargo"]);
+        assert_eq!(shell_program(OsStr::new("LANG=C pacman -Qq")), None);
+    }
+}
+
+/// Tests `bootstrap()`.
+#[cfg(test)]
+mod bootstrapping {
+    use std::fs;
+    use std::path::Path;
+    use std::process::Command;
+
+    use test_utils::TempFolder;
+
+    use super::*;
+
+    /// Test parsing the optional check and bootstrap commands.
+    #[test]
+    fn parse() {
+        let package_manager = CommandBackend::from_toml_string(include_str!("./files/bootstrap.toml"), "").unwrap();
+        assert_eq!(package_manager.check_command.unwrap().get_program(), "yay");
+        assert_eq!(package_manager.bootstrap_command.unwrap().get_program(), "sh");
+    }
+
+    /// The bootstrap command runs when the check command fails.
+    #[test]
+    fn bootstraps_missing() {
+        let temp_folder = TempFolder::new("bootstraps_missing");
+        let marker = temp_folder.path().join("marker");
+        let mut package_manager = package_manager(&marker);
+        assert!(!package_manager.is_present());
+        package_manager.bootstrap().unwrap();
+        assert!(package_manager.is_present());
+    }
+
+    /// The bootstrap command does not run when the check command succeeds.
+    #[test]
+    fn skips_present() {
+        let temp_folder = TempFolder::new("skips_present");
+        let marker = temp_folder.path().join("marker");
+        fs::write(&marker, "").unwrap();
+        let mut package_manager = package_manager(&marker);
+        // make the bootstrap command fail so running it would be an error
+        package_manager.bootstrap_command = Some(Command::new("false"));
+        package_manager.bootstrap().unwrap();
+    }
+
+    /// A bootstrap command that doesn't make the package manager present is an error.
+    #[test]
+    #[should_panic]
+    fn still_missing() {
+        let mut package_manager = package_manager(Path::new("definitely-does-not-exist"));
+        package_manager.bootstrap_command = Some(Command::new("true"));
+        package_manager.bootstrap().unwrap();
+    }
+
+    /// Creates a package manager that is present when `marker` exists and
+    /// bootstraps by creating `marker`.
+    fn package_manager(marker: &Path) -> CommandBackend {
+        let mut package_manager = CommandBackend::build(Command::new(""), Command::new(""));
+        let mut check_command = Command::new("test");
+        check_command.args(["-e".as_ref(), marker.as_os_str()]);
+        let mut bootstrap_command = Command::new("touch");
+        bootstrap_command.arg(marker);
+        package_manager.check_command = Some(check_command);
+        package_manager.bootstrap_command = Some(bootstrap_command);
+        package_manager
+    }
+}
+
+/// Tests `install()`.
+#[cfg(test)]
+mod installation {
+    use std::process::Command;
+
+    use super::*;
+
+    /// Packages that fail on their own are reported and everything else is installed.
+    #[test]
+    fn isolates_failures() {
+        let mut install_command = Command::new("sh");
+        install_command.args(["-c", "for p; do case $p in bad*) echo \"unknown $p\" >&2; exit 3;; esac; done", "sh"]);
+        let mut package_manager = CommandBackend::build(install_command, Command::new(""));
+        let report = package_manager.install(&packages(&["a", "bad1", "b", "c", "bad2"])).unwrap();
+        assert_eq!(report.installed, ["a", "b", "c"]);
+        assert_eq!(
+            report.failed,
+            ["bad1", "bad2"].map(|package| PackageFailure {
+                package: package.to_string(),
+                exit_code: Some(3),
+                stderr: format!("unknown {}", package),
+            })
+        );
+        assert!(!report.is_success());
+    }
+
+    /// The install command's arguments don't pile up between calls.
+    #[test]
+    fn repeated() {
+        let mut install_command = Command::new("sh");
+        install_command.args(["-c", "test $# -eq 1", "sh"]);
+        let mut package_manager = CommandBackend::build(install_command, Command::new(""));
+        assert!(package_manager.install(&packages(&["a"])).unwrap().is_success());
+        assert!(package_manager.install(&packages(&["b"])).unwrap().is_success());
+    }
+}
+
+/// Tests the `env`, `cwd` and `stdin` command options.
+#[cfg(test)]
+mod command_options {
+    use std::ffi::OsStr;
+    use std::path::Path;
+    use std::process::Command;
+
+    use super::*;
+
+    /// Test parsing `env` and `cwd`.
+    #[test]
+    fn parse() {
+        let package_manager = CommandBackend::from_toml_string(include_str!("./files/options.toml"), "").unwrap();
+        let envs = package_manager.install_command.get_envs().collect::<Vec<_>>();
+        assert_eq!(envs, [(OsStr::new("DEBIAN_FRONTEND"), Some(OsStr::new("noninteractive")))]);
+        assert_eq!(package_manager.install_command.get_current_dir(), Some(Path::new("/")));
+        assert!(!package_manager.packages_on_stdin);
+    }
+
+    /// A relative `cwd` is relative to the package manager file's folder.
+    #[test]
+    fn relative_cwd() {
+        let folder = Path::new(env!("CARGO_MANIFEST_DIR"));
+        let contents = include_str!("./files/options.toml").replace("cwd = \"/\"", "cwd = \"src\"");
+        let package_manager = CommandBackend::from_toml_string(&contents, folder).unwrap();
+        assert_eq!(package_manager.install_command.get_current_dir(), Some(folder.join("src").as_path()));
+        // the folder doesn't exist relative to the current directory
+        assert!(CommandBackend::from_toml_string(&contents, folder.join("tests")).is_err());
+    }
+
+    /// Test the options that fail validation.
+    #[test]
+    fn invalid() {
+        let valid = include_str!("./files/options.toml");
+        for invalid in [
+            valid.replace("DEBIAN_FRONTEND", "\"A=B\""),
+            valid.replace("cwd = \"/\"", "cwd = \"/definitely/does/not/exist\""),
+            valid.replace("[list_command]", "[list_command]\nstdin = \"packages\""),
+            valid.replace("cwd = \"/\"", "stdin = \"everything\""),
+        ] {
+            assert!(CommandBackend::from_toml_string(&invalid, "").is_err(), "{}", invalid);
+        }
+    }
+
+    /// Packages are written to stdin instead of passed as arguments.
+    #[test]
+    fn packages_on_stdin() {
+        let mut install_command = Command::new("sh");
+        // succeed only if there are no arguments and stdin has both packages
+        install_command.args(["-c", "test $# -eq 0 && test \"$(cat)\" = \"$(printf 'a\\nb')\"", "sh"]);
+        let mut package_manager = CommandBackend::build(install_command, Command::new(""));
+        package_manager.packages_on_stdin = true;
+        assert!(package_manager.install(&packages(&["a", "b"])).unwrap().is_success());
+    }
+
+    /// A command that fills the stderr pipe before reading stdin doesn't block
+    /// forever.
+    #[test]
+    fn large_stderr_before_stdin() {
+        let mut install_command = Command::new("sh");
+        install_command.args(["-c", "head -c 200000 /dev/zero >&2 && test \"$(wc -l)\" -eq 20000", "sh"]);
+        let mut package_manager = CommandBackend::build(install_command, Command::new(""));
+        package_manager.packages_on_stdin = true;
+        let packages = (0..20000).map(|index| format!("package-{}", index)).collect::<Vec<_>>();
+        assert!(package_manager.install(&packages).unwrap().is_success());
+    }
+}
+
+/// Tests the `{packages}` and `{package}` placeholders.
+#[cfg(test)]
+mod placeholders {
+    use std::process::Command;
+
+    use super::*;
+
+    /// Build a package manager whose install command has `args`.
+    fn package_manager(args: &[&str]) -> CommandBackend {
+        let mut install_command = Command::new("flatpak");
+        install_command.args(args);
+        CommandBackend::build(install_command, Command::new(""))
+    }
+
+    /// Tests `install_args()`.
+    #[test]
+    fn install_args() {
+        let packages = [String::from("a"), String::from("b")];
+        let appended = package_manager(&["install", "flathub"]);
+        assert_eq!(appended.install_args(&packages), ["install", "flathub", "a", "b"]);
+        let batch = package_manager(&["install", "flathub", "{packages}", "--user"]);
+        assert_eq!(batch.install_args(&packages), ["install", "flathub", "a", "b", "--user"]);
+        let single = package_manager(&["--install-extension={package}"]);
+        assert_eq!(single.install_args(&packages[..1]), ["--install-extension=a"]);
+        assert!(single.is_per_package() && !batch.is_per_package());
+    }
+
+    /// `{package}` runs the install command once per package.
+    #[test]
+    fn per_package() {
+        let mut install_command = Command::new("sh");
+        install_command.args([
+            "-c",
+            "test $# -eq 2 && test \"$1\" = --install-extension",
+            "sh",
+            "--install-extension",
+            "{package}",
+        ]);
+        let mut package_manager = CommandBackend::build(install_command, Command::new(""));
+        assert_eq!(package_manager.install(&packages(&["a", "b"])).unwrap().installed, ["a", "b"]);
+    }
+
+    /// Test the placeholder combinations that fail validation.
+    #[test]
+    fn invalid() {
+        let valid = include_str!("./files/valid.toml");
+        for invalid in [
+            valid.replace("\"--noconfirm\"]", "\"{packages}\", \"{package}\"]"),
+            valid.replace("\"--noconfirm\"]", "\"{packages}\"]\nper_package = true"),
+            valid.replace("\"--noconfirm\"]", "\"{packages}\"]\nstdin = \"packages\""),
+            valid.replace("[\"-Qqen\"]", "[\"{package}\"]"),
+            valid.replace("\"--noconfirm\"]", "\"--pkgs={packages}\"]"),
+        ] {
+            assert!(CommandBackend::from_toml_string(&invalid, "").is_err(), "{}", invalid);
+        }
+    }
+}
+
+/// Tests `shell` commands.
+#[cfg(test)]
+mod shell {
+    use std::process::Command;
+
+    use super::*;
+
+    /// Shell commands run through `sh -c` and are flagged.
+    #[test]
+    fn parse() {
+        let package_manager = CommandBackend::from_toml_string(include_str!("./files/shell.toml"), "").unwrap();
+        assert_eq!(package_manager.list_command.get_program(), "sh");
+        assert_eq!(package_manager.list_command.get_args().collect::<Vec<_>>(), ["-c", "cargo install --list | grep -E '^[a-z]'", "sh"]);
+        assert_eq!(package_manager.shell_tables, ["install_command", "list_command"]);
+    }
+
+    /// Packages are quoted in place of the placeholders, or passed as positional parameters.
+    #[test]
+    fn quoting() {
+        let packages = [String::from("it's"), String::from("b")];
+        let positional = CommandBackend::from_toml_string(include_str!("./files/shell.toml"), "").unwrap();
+        assert_eq!(positional.install_args(&packages), ["-c", "cargo install \"$@\"", "sh", "it's", "b"]);
+        let placeholder = CommandBackend::from_toml_string(include_str!("./files/shell.toml").replace("\\\"$@\\\"", "{packages}"), "").unwrap();
+        assert_eq!(placeholder.install_args(&packages), ["-c", "cargo install 'it'\\''s' 'b'", "sh"]);
+    }
+
+    /// A quoted package list reaches the command intact.
+    #[test]
+    fn install() {
+        let mut package_manager = CommandBackend::from_toml_string(include_str!("./files/valid.toml"), "").unwrap();
+        let mut install_command = Command::new("sh");
+        install_command.args(["-c", "set -- {packages}; test $# -eq 2 && test \"$1\" = \"it's\"", "sh"]);
+        package_manager.install_command = install_command;
+        package_manager.shell_tables = vec!["install_command"];
+        assert!(package_manager.install(&packages(&["it's", "b c"])).unwrap().is_success());
+    }
+
+    /// Test the combinations that fail validation.
+    #[test]
+    fn invalid() {
+        let valid = include_str!("./files/shell.toml");
+        for invalid in [
+            valid.replace("shell = \"cargo install \\\"$@\\\"\"", "args = []"),
+            valid.replace("shell = \"cargo install \\\"$@\\\"\"", "shell = \"cargo\"\ncommand = \"cargo\""),
+            valid.replace("shell = \"cargo install \\\"$@\\\"\"", "shell = \"cargo\"\nargs = [\"install\"]"),
+        ] {
+            assert!(CommandBackend::from_toml_string(&invalid, "").is_err(), "{}", invalid);
+        }
+    }
+}
+
+/// Tests running commands as root.
+#[cfg(test)]
+mod root {
+    use super::*;
+    use crate::pac::Privilege;
+    use crate::pac::privilege::is_root;
+
+    /// Only the commands marked with `root = true` are wrapped.
+    #[test]
+    fn wraps_root_commands() {
+        let mut package_manager = CommandBackend::from_toml_string(include_str!("../../../templates/presets/apt.toml"), "").unwrap();
+        package_manager.set_privilege(Privilege::Doas);
+        assert_eq!(package_manager.root_tables, ["install_command"]);
+        assert!(package_manager.needs_root());
+        assert_eq!(package_manager.notes(), ["`install_command` runs as root"]);
+        let install_command = package_manager.prepare("install_command", &package_manager.install_command, ["vim"]);
+        let list_command = package_manager.prepare("list_command", &package_manager.list_command, package_manager.list_command.get_args());
+        assert_eq!(install_command.get_program(), if is_root() { "apt-get" } else { "doas" });
+        assert_eq!(list_command.get_program(), "apt-mark");
     }
 }
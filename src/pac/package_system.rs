@@ -1,12 +1,22 @@
-use std::collections::HashSet;
+use std::collections::{BTreeSet, HashSet};
 use std::fs::{self, File, OpenOptions};
+use std::io::ErrorKind;
 use std::io::prelude::*;
-use std::io::{ErrorKind, LineWriter};
 use std::path::{Path, PathBuf};
 
-use utils;
+use itertools::Itertools;
+use serde_derive::Deserialize;
 
+use super::all::Operation;
+use super::exclusion::{Exclusion, Exclusions};
+use super::package_list::PackageList;
 use super::package_manager::*;
+use super::presets;
+use super::privilege::Privilege;
+use super::status::{Status, UploadPlan};
+use super::template::{TemplateContext, detect_hostname};
+use super::trust::command_tables;
+use crate::Error;
 
 #[cfg(test)]
 mod tests;
@@ -14,13 +24,49 @@ mod tests;
 pub const PACKAGE_MANAGER_FILENAME: &str = "package-manager.toml";
 pub const PACKAGES_FILENAME: &str = "installed-packages.txt";
 pub const EXCLUDED_PACKAGES_FILENAME: &str = "excluded-packages.txt";
+/// Holds what each host recorded on its last upload, one file per hostname.
+pub const SNAPSHOTS_FOLDERNAME: &str = ".snapshots";
+/// Holds the package files of every layer except `common`, one file per layer.
+pub const LAYERS_FOLDERNAME: &str = "layers";
+/// The layer used by every host, stored in the packages file.
+pub const COMMON_LAYER: &str = "common";
+/// The host layer's name when the hostname can't be detected.
+const UNKNOWN_HOST: &str = "unknown-host";
+
+/// Package system settings that are read from the package manager file
+/// alongside the `PackageManager` itself.
+#[derive(Deserialize, Default, Debug, PartialEq)]
+pub struct PackageSystemSettings {
+    /// Use this package system when none is specified.
+    #[serde(default)]
+    pub default: bool,
+    /// Package systems that must be run before this one.
+    #[serde(default)]
+    pub requires: Vec<String>,
+    /// How to run commands as root, overriding the global setting.
+    #[serde(default)]
+    pub privilege: Option<Privilege>,
+}
 
 // Contains a `PackageManager` and the files that store package information.
+//
+// Recorded packages are split into layers that combine into the packages for
+// the current host:
+// - `common` - the packages file, used by every host.
+// - Profiles such as `laptop` or `work` - **layers/<profile>.txt**, used by
+//   hosts that list the profile.
+// - The host layer - **layers/<hostname>.txt**, used only by this host.
 pub struct PackageSystem {
     name: String,
     package_manager: PackageManager,
+    settings: PackageSystemSettings,
+    folder: PathBuf,
     packages_file: PathBuf,
     excluded_packages_file: PathBuf,
+    hostname: String,
+    profiles: Vec<String>,
+    upload_layer: Option<String>,
+    commands: String,
 }
 impl PackageSystem {
     /// Build a new `PackageSystem`.
@@ -33,129 +79,407 @@ impl PackageSystem {
         let folder = folder.as_ref();
         PackageSystem {
             name,
+            folder: folder.to_path_buf(),
             packages_file: folder.join(PACKAGES_FILENAME),
             excluded_packages_file: folder.join(EXCLUDED_PACKAGES_FILENAME),
-            package_manager: package_manager,
+            hostname: detect_hostname(),
+            profiles: Vec::new(),
+            upload_layer: None,
+            commands: String::new(),
+            package_manager,
+            settings: PackageSystemSettings::default(),
         }
     }
 
-    /// Build from a folder alone.
+    /// Build from a folder alone. The package manager file is rendered as a
+    /// template first, with user variables from the parent folder's
+    /// `variables.toml` (see `TemplateContext`).
     ///
     /// # Errors
     /// - Failed to read from `folder`.
     /// - An invalid file was detected.
-    /// - Any errors from `PackageManager::from_toml()`.
+    /// - Any errors from `TemplateContext::detect_with_variables()`.
+    /// - Any errors from `TemplateContext::render()`.
+    /// - Any errors from `PackageManager::from_toml_string()`.
+    /// - The package system settings are invalid.
     /// - No package manager file was found.
     ///
     /// # Folder Contents
     /// The folder must contain:
-    /// - `package_manager.toml` - Info about the package manager.
+    /// - `package_manager.toml` - Info about the package manager. May also
+    ///   contain `PackageSystemSettings` keys, such as `default = true`.
     ///
     /// The folder *should* contain:
     /// - `installed_packages.txt` - A list of installed packages.
     /// - `excluded_packages.txt` - A list of excluded packages.
     ///
+    /// The folder *may* contain:
+    /// - `layers/` - Package lists for profiles and hosts.
+    ///
     /// If the above two aren't present, this function could still succeed, but
     /// the package system will fail later.
     //# UNIT TESTED
-    pub fn from_folder(folder: impl AsRef<Path>) -> Result<Self, String> {
+    pub fn from_folder(folder: impl AsRef<Path>) -> Result<Self, Error> {
+        let folder = folder.as_ref();
+        let context = TemplateContext::detect_with_variables(folder.parent().unwrap_or(Path::new("")))?;
+        Self::from_folder_with_context(folder, &context)
+    }
+
+    /// Build from a folder, rendering the package manager file with `context`.
+    ///
+    /// # Errors
+    /// - The same as `from_folder()`.
+    //# INTEGRATION TESTED
+    pub fn from_folder_with_context(folder: impl AsRef<Path>, context: &TemplateContext) -> Result<Self, Error> {
         let folder = folder.as_ref();
-        // get the package manager from a file
+        // get the package manager and settings from a file
         let package_manager_file = folder.join(PACKAGE_MANAGER_FILENAME);
-        let package_manager = PackageManager::from_toml(&package_manager_file)?;
+        let template = fs::read_to_string(&package_manager_file).map_err(|e| Error::io("read", &package_manager_file, e))?;
+        let (package_manager, settings, commands) = context
+            .render(&template)
+            .and_then(|contents| {
+                Ok((
+                    PackageManager::from_toml_string(&contents, folder)?,
+                    toml::from_str::<PackageSystemSettings>(&contents)?,
+                    command_tables(&contents)?,
+                ))
+            })
+            .map_err(|e| e.in_file(&package_manager_file))?;
         // get the package system's name from the folder name
-        let name = match folder.file_name() {
-            Some(name_os) => name_os.to_string_lossy().to_string(),
-            _ => {
-                return Err(format!("Invalid package system folder: {}", folder.to_string_lossy()));
-            }
+        let Some(name) = folder.file_name().map(|name| name.to_string_lossy().to_string()) else {
+            return Err(Error::Usage(format!("Invalid package system folder: {}", folder.to_string_lossy())));
         };
 
-        Ok(Self::build(name, folder, package_manager))
+        let mut package_system = Self::build(name, folder, package_manager);
+        if let Some(privilege) = settings.privilege {
+            package_system.package_manager.set_privilege(privilege);
+        }
+        package_system.settings = settings;
+        package_system.commands = commands;
+        Ok(package_system)
+    }
+
+    /// Get the names of the layers used on this host: `common`, then the
+    /// profiles, then the host layer.
+    pub fn layers(&self) -> Vec<&str> {
+        let mut layers = vec![COMMON_LAYER];
+        for layer in self.profiles.iter().map(String::as_str).chain([self.host_layer()]) {
+            if !layers.contains(&layer) {
+                layers.push(layer);
+            }
+        }
+        layers
     }
 
-    /// Read this package system's packages file. If the file does not exist, create it.
+    /// Get the name of this host's layer.
+    pub fn host_layer(&self) -> &str {
+        if self.hostname.is_empty() { UNKNOWN_HOST } else { &self.hostname }
+    }
+
+    /// Use the layers of `profiles` on this host.
+    pub fn set_profiles(&mut self, profiles: Vec<String>) {
+        self.profiles = profiles;
+    }
+
+    /// Record newly installed packages into `layer` on `upload()` instead of
+    /// the host layer.
+    ///
+    /// # Errors
+    /// - `layer` is not used on this host.
+    //# UNIT TESTED
+    pub fn set_upload_layer(&mut self, layer: &str) -> Result<&mut Self, Error> {
+        let layers = self.layers();
+        if !layers.contains(&layer) {
+            return Err(Error::Usage(format!("Layer `{}` is not used on this host. Available: {}", layer, layers.join(", "))));
+        }
+        self.upload_layer = Some(layer.to_string());
+        Ok(self)
+    }
+
+    /// Get the path of `layer`'s package file.
+    pub fn layer_file(&self, layer: &str) -> PathBuf {
+        if layer == COMMON_LAYER {
+            self.packages_file.clone()
+        } else {
+            self.folder.join(LAYERS_FOLDERNAME).join(format!("{}.txt", layer))
+        }
+    }
+
+    /// Get the path of this host's snapshot of its last upload.
+    fn snapshot_file(&self) -> PathBuf {
+        self.folder.join(SNAPSHOTS_FOLDERNAME).join(format!("{}.txt", self.host_layer()))
+    }
+
+    /// Read the package file of every layer used on this host, in the order of
+    /// `layers()`. If the packages file does not exist, create it. Other
+    /// missing layers are treated as empty.
     ///
     /// ## Errors
-    /// - The file des not exist.
+    /// - The packages file does not exist and cannot be created.
+    /// - A layer's file cannot be read.
+    //# UNIT TESTED
+    fn read_layers(&self) -> Result<Vec<PackageList>, Error> {
+        self.layers()
+            .into_iter()
+            .map(|layer| match layer {
+                COMMON_LAYER => read_or_create_package_file(&self.packages_file),
+                layer => read_package_file_if_exists(&self.layer_file(layer)),
+            })
+            .collect()
+    }
+
+    /// Read this package system's excluded packages file. If the file does not exist, create it.
+    ///
+    /// ## Errors
+    /// - The file does not exist and cannot be created.
     /// - The file cannot be read.
-    fn read_packages_file(&self) -> Result<HashSet<String>, String> {
-        todo!()
+    //# UNIT TESTED
+    fn read_excluded_packages_file(&self) -> Result<PackageList, Error> {
+        read_or_create_package_file(&self.excluded_packages_file)
     }
 
-    /// Attempt to install packages from the package file.
+    /// Attempt to install packages from every layer used on this host that
+    /// are not already installed.
     /// # Errors
-    /// - The packages file could not be read.
-    /// - The install command failed.
-    //# HELPERS TESTED
-    pub fn install(&mut self) -> Result<&mut Self, String> {
-        let packages = match utils::read_file_to_hashset(&self.packages_file) {
-            Ok(v) => v,
-            Err(e) => {
-                if e.kind() == ErrorKind::NotFound {
-                    println!("Packages file does not exist, creating");
-                    if let Err(e) = OpenOptions::new().write(true).create_new(true).open(&self.packages_file) {
-                        return Err(format!(
-                            "Failed to create packages file `{}`: {}",
-                            &self.packages_file.to_string_lossy().to_string(),
-                            e.to_string()
-                        ));
-                    }
-                    return Ok(self);
-                }
-                return Err(format!("Failed to read packages file: {}", self.packages_file.to_string_lossy()));
-            }
-        };
-        self.package_manager.install(&packages)?;
-        println!("Successfully installed packages!");
+    /// - Any errors from `read_layers()`.
+    /// - Any errors from `PackageManager.install()`.
+    /// - Any packages failed to install.
+    //# UNIT TESTED
+    pub fn install(&mut self) -> Result<&mut Self, Error> {
+        let packages = self.read_layers()?.iter().flat_map(PackageList::packages).collect::<HashSet<_>>();
+        if packages.is_empty() {
+            println!("No packages to install");
+            return Ok(self);
+        }
+        let report = self.package_manager.install(packages.iter().sorted())?;
+        if !report.is_success() {
+            return Err(Error::Failed(report.to_string()));
+        }
+        if report.installed.is_empty() {
+            println!("All {} packages are already installed", report.already_installed.len());
+        } else {
+            println!("Successfully installed {} packages! {} were already installed", report.installed.len(), report.already_installed.len());
+        }
 
         Ok(self)
     }
 
-    /// Upload a list of installed packages to the packages file, excluding
-    /// packages in the excluded packages file.
+    /// Merge this host's changes into its layers. Packages installed since
+    /// this host's last upload that no layer records yet are added to the
+    /// upload layer (the host layer unless `set_upload_layer()` was called).
+    /// Packages uninstalled since then are removed from every layer used on
+    /// this host, so packages recorded by other hosts are left alone.
+    /// Packages matched by the excluded packages file are never added.
+    /// Comments, sections and annotations already in the files are kept.
     /// # Errors
     /// - The list command failed.
-    /// - The excluded packages file could not be read.
-    /// - The packages file could not be created or truncated.
-    /// - The packages file could not be written to.
-    //# INTEGRATION TESTED
-    pub fn upload(&mut self) -> Result<&mut Self, String> {
+    /// - The layers, excluded packages file or snapshot could not be read.
+    /// - The excluded packages file has an invalid pattern.
+    /// - The layers or snapshot could not be written to.
+    //# UNIT TESTED
+    pub fn upload(&mut self) -> Result<&mut Self, Error> {
         let installed_packages = self.package_manager.list()?;
-        // get the list of excluded packages
-        let Ok(excluded_packages) = utils::read_file_to_hashset(&self.excluded_packages_file) else {
-            return Err(format!("Failed to read excluded packages file `{}`", &self.excluded_packages_file.to_string_lossy()));
-        };
-        // create/truncate the packages file
-        let mut packages_file = if let Ok(file) = File::create(&self.packages_file) {
-            LineWriter::new(file)
-        } else {
-            return Err(format!("Failed to create or truncate packages file `{}`", &self.packages_file.to_string_lossy()));
-        };
-        // exclude packages
-        // write the updated package list to the file
-        for package in installed_packages.difference(&excluded_packages) {
-            if writeln!(packages_file, "{}", package).is_err() {
-                return Err(format!("Failed to write to packages file `{}`", &self.packages_file.to_string_lossy()));
+        let merge = self.merge(self.read_layers()?, &self.read_excluded_packages_file()?, &installed_packages)?;
+        self.write_layers(&merge.layers, &merge.changed())?;
+        self.write_snapshot(&merge.uploaded)?;
+        Ok(self)
+    }
+
+    /// Work out what `upload()` would change without changing anything. With
+    /// `after_install`, the packages `install()` would install count as
+    /// installed, like they would during `sync()`.
+    ///
+    /// # Errors
+    /// - Any errors from `PackageManager.list()`.
+    /// - A layer, the excluded packages file or the snapshot exists but cannot
+    ///   be read.
+    /// - The excluded packages file has an invalid pattern.
+    //# UNIT TESTED
+    pub fn plan_upload(&mut self, after_install: bool) -> Result<UploadPlan, Error> {
+        let mut installed_packages = self.package_manager.list()?;
+        let layers = self
+            .layers()
+            .into_iter()
+            .map(|layer| read_package_file_if_exists(&self.layer_file(layer)))
+            .collect::<Result<Vec<_>, _>>()?;
+        if after_install {
+            installed_packages.extend(layers.iter().flat_map(PackageList::packages));
+        }
+        let merge = self.merge(layers, &read_package_file_if_exists(&self.excluded_packages_file)?, &installed_packages)?;
+        let mut plan = UploadPlan::default();
+        for ((layer, recorded), removed) in self.layers().into_iter().zip(merge.recorded).zip(merge.removed) {
+            if !recorded.is_empty() {
+                plan.recorded.push((self.relative_layer_file(layer), recorded));
+            }
+            if !removed.is_empty() {
+                plan.removed.push((self.relative_layer_file(layer), removed));
             }
         }
-        Ok(self)
+        Ok(plan)
+    }
+
+    /// Describe what `operation` would change without running it.
+    ///
+    /// # Errors
+    /// - Any errors from `status()`.
+    /// - Any errors from `plan_upload()`.
+    //# UNIT TESTED
+    pub fn plan(&mut self, operation: Operation) -> Result<String, Error> {
+        let mut lines = Vec::new();
+        if matches!(operation, Operation::Install | Operation::Sync) {
+            lines.push(self.status()?.install_plan());
+        }
+        if matches!(operation, Operation::Upload | Operation::Sync) {
+            lines.push(self.plan_upload(operation == Operation::Sync)?.to_string());
+        }
+        Ok(lines.join("\n"))
+    }
+
+    /// Merge this host's changes since its last upload into `layers`, the
+    /// package files of `layers()` (see `upload()`). Installed packages
+    /// matched by `excluded` are left out.
+    ///
+    /// # Errors
+    /// - The snapshot exists but cannot be read.
+    /// - `excluded` has an invalid pattern.
+    fn merge(&self, mut layers: Vec<PackageList>, excluded: &PackageList, installed_packages: &HashSet<String>) -> Result<Merge, Error> {
+        let excluded_packages = Exclusions::from_list(excluded)?.matching(installed_packages);
+        let uploaded = installed_packages.difference(&excluded_packages).cloned().collect::<HashSet<_>>();
+        let snapshot = read_package_file_if_exists(&self.snapshot_file())?.packages();
+        let upload_layer = self.upload_layer.as_deref().unwrap_or(self.host_layer());
+        let upload_index = self.layers().iter().position(|layer| *layer == upload_layer).unwrap_or(layers.len() - 1);
+        let mut recorded = vec![BTreeSet::new(); layers.len()];
+        let mut removed = vec![BTreeSet::new(); layers.len()];
+        for package in uploaded.difference(&snapshot).sorted() {
+            if !layers.iter().any(|layer| layer.contains(package)) && layers[upload_index].insert(package) {
+                recorded[upload_index].insert(package.clone());
+            }
+        }
+        for package in snapshot.difference(&uploaded) {
+            for (index, layer) in layers.iter_mut().enumerate() {
+                if layer.remove(package) {
+                    removed[index].insert(package.clone());
+                }
+            }
+        }
+        Ok(Merge {
+            layers,
+            uploaded,
+            recorded,
+            removed,
+        })
+    }
+
+    /// Get the path of `layer`'s package file relative to the package system
+    /// folder.
+    fn relative_layer_file(&self, layer: &str) -> String {
+        let path = self.layer_file(layer);
+        path.strip_prefix(&self.folder).unwrap_or(&path).to_string_lossy().to_string()
+    }
+
+    /// Write every layer from `read_layers()` whose entry in `changed` is set.
+    ///
+    /// # Errors
+    /// - The layers folder could not be created.
+    /// - A layer's file could not be written to.
+    fn write_layers(&self, packages: &[PackageList], changed: &[bool]) -> Result<(), Error> {
+        for ((layer, packages), _) in self.layers().into_iter().zip(packages).zip(changed).filter(|(_, changed)| **changed) {
+            let path = self.layer_file(layer);
+            if let Some(folder) = path.parent() {
+                fs::create_dir_all(folder).map_err(|e| Error::io("create layers folder", folder, e))?;
+            }
+            write_package_file(&path, packages)?;
+        }
+        Ok(())
+    }
+
+    /// Record `packages` as this host's last upload.
+    ///
+    /// # Errors
+    /// - The snapshots folder could not be created.
+    /// - The snapshot could not be written to.
+    fn write_snapshot(&self, packages: &HashSet<String>) -> Result<(), Error> {
+        let path = self.snapshot_file();
+        if let Some(folder) = path.parent() {
+            fs::create_dir_all(folder).map_err(|e| Error::io("create snapshots folder", folder, e))?;
+        }
+        let mut snapshot = PackageList::default();
+        snapshot.set_packages(packages);
+        write_package_file(&path, &snapshot)
+    }
+
+    /// Compare the layers used on this host and the excluded packages file
+    /// against the installed packages without changing anything.
+    ///
+    /// # Errors
+    /// - Any errors from `PackageManager.list()`.
+    /// - A layer or the excluded packages file exists but cannot be read.
+    /// - The excluded packages file has an invalid pattern.
+    //# UNIT TESTED
+    pub fn status(&mut self) -> Result<Status, Error> {
+        let installed_packages = self.package_manager.list()?;
+        let mut packages = HashSet::new();
+        for layer in self.layers() {
+            packages.extend(read_package_file_if_exists(&self.layer_file(layer))?.packages());
+        }
+        let excluded_packages = Exclusions::from_list(&read_package_file_if_exists(&self.excluded_packages_file)?)?.matching(&installed_packages);
+        let snapshot = read_package_file_if_exists(&self.snapshot_file())?.packages();
+        Ok(Status::new(&packages, &excluded_packages, &installed_packages, &snapshot))
     }
 
     /// Calls `install()` followed by `upload()`.
     //# HELPERS TESTED
-    pub fn sync(&mut self) -> Result<&mut Self, String> {
+    pub fn sync(&mut self) -> Result<&mut Self, Error> {
         self.install()?;
         self.upload()?;
-        return Ok(self);
+        Ok(self)
     }
 
-    /// Exclude packages from `upload()`. Warns the user if any packages that
-    /// are about to be excluded are not installed.
+    /// Exclude packages or patterns (see `Exclusion`) from `upload()`. Warns
+    /// the user if any packages that are about to be excluded are not
+    /// installed or are already excluded. Newly excluded packages are also
+    /// removed from every layer used on this host.
     ///
     /// # Errors
     /// - Any errors from `PackageManager.list()`.
-    pub fn exclude(&mut self) -> Result<&mut Self, String> {
+    /// - Any errors from `Exclusion::parse()`.
+    /// - A layer or the excluded packages file could not be read or written.
+    //# UNIT TESTED
+    pub fn exclude(&mut self, packages: impl IntoIterator<Item = impl AsRef<str>>) -> Result<&mut Self, Error> {
+        let installed_packages = self.package_manager.list()?;
+        let mut excluded_packages = self.read_excluded_packages_file()?;
+        let mut newly_excluded = Vec::new();
+        for package in packages {
+            let package = package.as_ref();
+            let exclusion = Exclusion::parse(package)?;
+            if exclusion.is_pattern() {
+                if !installed_packages.iter().any(|installed| exclusion.matches(installed)) {
+                    println!("Warning: pattern `{}` does not match any installed packages", package);
+                }
+            } else if !installed_packages.contains(package) {
+                println!("Warning: package `{}` is not installed", package);
+            }
+            if excluded_packages.insert(package) {
+                newly_excluded.push(exclusion);
+            } else {
+                println!("Warning: package `{}` is already excluded", package);
+            }
+        }
+        if newly_excluded.is_empty() {
+            return Ok(self);
+        }
+        write_package_file(&self.excluded_packages_file, &excluded_packages)?;
+        // drop the newly excluded packages from every layer right away
+        let mut packages = self.read_layers()?;
+        let mut changed = vec![false; packages.len()];
+        for (index, layer) in packages.iter_mut().enumerate() {
+            for package in layer.packages() {
+                if newly_excluded.iter().any(|exclusion| exclusion.matches(&package)) {
+                    changed[index] |= layer.remove(&package);
+                }
+            }
+        }
+        self.write_layers(&packages, &changed)?;
         Ok(self)
     }
 
@@ -164,10 +488,45 @@ impl PackageSystem {
     ///
     /// # Errors
     /// - Any errors from `PackageManager.list()`.
-    pub fn reinclude(&mut self) -> Result<&mut Self, String> {
+    /// - The excluded packages file could not be read or written.
+    //# UNIT TESTED
+    pub fn reinclude(&mut self, packages: impl IntoIterator<Item = impl AsRef<str>>) -> Result<&mut Self, Error> {
+        let installed_packages = self.package_manager.list()?;
+        let mut excluded_packages = self.read_excluded_packages_file()?;
+        let mut changed = false;
+        for package in packages {
+            let package = package.as_ref();
+            if !installed_packages.contains(package) {
+                println!("Warning: package `{}` is not installed", package);
+            }
+            if excluded_packages.remove(package) {
+                changed = true;
+            } else {
+                println!("Warning: package `{}` was not excluded", package);
+            }
+        }
+        if changed {
+            write_package_file(&self.excluded_packages_file, &excluded_packages)?;
+        }
         Ok(self)
     }
 
+    /// Get the installed packages that `pattern` would exclude, in sorted order.
+    ///
+    /// # Errors
+    /// - Any errors from `Exclusion::parse()`.
+    /// - Any errors from `PackageManager.list()`.
+    //# UNIT TESTED
+    pub fn test_exclusion(&mut self, pattern: &str) -> Result<Vec<String>, Error> {
+        let exclusion = Exclusion::parse(pattern)?;
+        let installed_packages = self.package_manager.list()?;
+        Ok(installed_packages
+            .into_iter()
+            .filter(|package| exclusion.matches(package))
+            .sorted()
+            .collect())
+    }
+
     /// Get the package system's name.
     pub fn name(&self) -> &String {
         &self.name
@@ -177,53 +536,156 @@ impl PackageSystem {
     pub fn package_manager(&self) -> &PackageManager {
         &self.package_manager
     }
+
+    /// Get the package manager mutably.
+    pub fn package_manager_mut(&mut self) -> &mut PackageManager {
+        &mut self.package_manager
+    }
+
+    /// Get the package system's settings.
+    pub fn settings(&self) -> &PackageSystemSettings {
+        &self.settings
+    }
+
+    /// Get the path of the excluded packages file.
+    pub fn excluded_packages_file(&self) -> &Path {
+        &self.excluded_packages_file
+    }
+
+    /// Get the package system's folder.
+    pub fn folder(&self) -> &Path {
+        &self.folder
+    }
+
+    /// Get the command tables of the rendered package manager file as
+    /// **TOML**, or an empty string if the package system was built in code.
+    pub fn commands(&self) -> &str {
+        &self.commands
+    }
 }
 
 #[cfg(debug_assertions)]
 impl PartialEq for PackageSystem {
     fn eq(&self, other: &Self) -> bool {
-        self.package_manager == other.package_manager
+        self.commands == other.commands
+            && self.package_manager.programs() == other.package_manager.programs()
             && self.packages_file == other.packages_file
             && self.excluded_packages_file == other.excluded_packages_file
+            && self.folder == other.folder
+            && self.hostname == other.hostname
+            && self.profiles == other.profiles
+            && self.upload_layer == other.upload_layer
+            && self.settings == other.settings
             && self.name == other.name
     }
 }
 
+/// The layers of a package system with this host's changes merged in (see
+/// `PackageSystem::upload()`).
+struct Merge {
+    /// The package files of every layer used on this host, in the order of
+    /// `PackageSystem::layers()`.
+    layers: Vec<PackageList>,
+    /// The installed packages that aren't excluded, which become the new
+    /// snapshot.
+    uploaded: HashSet<String>,
+    /// The packages added to each layer.
+    recorded: Vec<BTreeSet<String>>,
+    /// The packages removed from each layer.
+    removed: Vec<BTreeSet<String>>,
+}
+impl Merge {
+    /// Whether each layer changed.
+    fn changed(&self) -> Vec<bool> {
+        self.recorded
+            .iter()
+            .zip(&self.removed)
+            .map(|(recorded, removed)| !recorded.is_empty() || !removed.is_empty())
+            .collect()
+    }
+}
+
+/// Read a package file. If the file does not exist, create it.
+///
+/// ## Errors
+/// - The file does not exist and cannot be created.
+/// - The file cannot be read or parsed.
+fn read_or_create_package_file(path: &Path) -> Result<PackageList, Error> {
+    match fs::read_to_string(path) {
+        Ok(contents) => parse_package_file(path, &contents),
+        Err(e) if e.kind() == ErrorKind::NotFound => {
+            println!("`{}` does not exist, creating", path.to_string_lossy());
+            if let Err(e) = OpenOptions::new().write(true).create_new(true).open(path) {
+                return Err(Error::io("create package file", path, e));
+            }
+            Ok(PackageList::default())
+        }
+        Err(e) => Err(Error::io("read package file", path, e)),
+    }
+}
+
+/// Read a package file. A missing file is treated as empty.
+///
+/// ## Errors
+/// - The file exists but cannot be read or parsed.
+fn read_package_file_if_exists(path: &Path) -> Result<PackageList, Error> {
+    match fs::read_to_string(path) {
+        Ok(contents) => parse_package_file(path, &contents),
+        Err(e) if e.kind() == ErrorKind::NotFound => Ok(PackageList::default()),
+        Err(e) => Err(Error::io("read package file", path, e)),
+    }
+}
+
+/// Helper function to parse the contents of the package file at `path`.
+fn parse_package_file(path: &Path, contents: &str) -> Result<PackageList, Error> {
+    contents.parse().map_err(|e: Error| e.in_file(path))
+}
+
+/// Overwrite a package file with `packages`.
+///
+/// ## Errors
+/// - The file cannot be created or written to.
+fn write_package_file(path: &Path, packages: &PackageList) -> Result<(), Error> {
+    fs::write(path, packages.to_string()).map_err(|e| Error::io("write to package file", path, e))
+}
+
 /// Create a new package system in `folder`.
 ///
 /// # Parameters
 /// - `package_systems_folder` - The full path to the folder to create the package system folder in.
 /// - `name` - The name of the new package manager.
+/// - `preset` - The name of a built-in preset to fill in the package manager
+///   file with. If not specified, an empty template is used.
 ///
 /// # Errors
+/// - `preset` is not a known preset.
 /// - Cannot create the package system folder.
 /// - Cannot create any package system files.
 //# INTEGRATION TESTED
-pub fn new_package_system(folder: impl AsRef<Path>, name: impl AsRef<str>) -> Result<(), String> {
+pub fn new_package_system(folder: impl AsRef<Path>, name: impl AsRef<str>, preset: Option<&str>) -> Result<(), Error> {
     let name = name.as_ref();
+    let template = match preset {
+        Some(preset) => presets::preset(preset)?,
+        None => include_str!("../../templates/package-manager.toml"),
+    };
     let folder = Path::new(folder.as_ref()).join(name);
     // check for an already-existing package system folder
     if folder.exists() {
-        return Err(format!("Package system `{}` already exists", name));
+        return Err(Error::Usage(format!("Package system `{}` already exists", name)));
     }
     // create the package system folder
-    if fs::create_dir_all(&folder).is_err() {
-        return Err(format!("Could not create package system folder `{}`", folder.to_string_lossy()));
-    };
+    fs::create_dir_all(&folder).map_err(|e| Error::io("create package system folder", &folder, e))?;
     // create the package files
     for file in [PACKAGES_FILENAME, EXCLUDED_PACKAGES_FILENAME] {
         let file = folder.join(file);
-        if File::create_new(&file).is_err() {
-            return Err(format!("Could not create package file `{}`. Maybe it already exists?", &file.to_string_lossy()));
-        }
+        File::create_new(&file).map_err(|e| Error::io("create package file", &file, e))?;
     }
     // create the package manager file
     let package_manager_path = folder.join(PACKAGE_MANAGER_FILENAME);
-    let Ok(package_manager_file) = File::create_new(&package_manager_path) else {
-        return Err(format!("Could not create package manager file `{}`. Maybe it already exists?", &package_manager_path.to_string_lossy()));
-    };
+    let package_manager_file =
+        File::create_new(&package_manager_path).map_err(|e| Error::io("create package manager file", &package_manager_path, e))?;
     // write to it
-    if write!(&package_manager_file, include_str!("../../templates/package-manager.toml")).is_err() {
+    if write!(&package_manager_file, "{}", template).is_err() {
         println!("Failed to write template for package manager file `{}`.", &package_manager_path.to_string_lossy())
     }
 
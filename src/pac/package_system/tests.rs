@@ -1,7 +1,25 @@
 use std::process::Command;
 
+use test_utils::TempFolder;
+
 use super::*;
 
+/// Build a package system for the host `host` in a temporary folder named
+/// `name`. Its list command reports `installed` as installed, and its install
+/// command writes its arguments to `installed.txt` in the folder.
+fn package_system(name: &str, installed: &str) -> (PackageSystem, TempFolder) {
+    let folder = TempFolder::new(name);
+    let mut install_command = Command::new("sh");
+    install_command
+        .args(["-c", "echo \"$@\" > \"$0\""])
+        .arg(folder.path().join("installed.txt"));
+    let mut list_command = Command::new("printf");
+    list_command.arg(installed);
+    let mut package_system = PackageSystem::build(name.to_string(), folder.path(), PackageManager::build(install_command, list_command));
+    package_system.hostname = String::from("host");
+    (package_system, folder)
+}
+
 /// Tests the constructors.
 #[cfg(test)]
 mod construction {
@@ -11,7 +29,7 @@ mod construction {
     #[test]
     fn build() {
         let folder = Path::new("package_systems");
-        let package_system = PackageSystem::build(String::from("pacman"), &folder, PackageManager::build(Command::new(""), Command::new("")));
+        let package_system = PackageSystem::build(String::from("pacman"), folder, PackageManager::build(Command::new(""), Command::new("")));
         // make sure the packages file and excluded packages file have the correct paths
         assert_eq!(package_system.packages_file, folder.join(super::PACKAGES_FILENAME));
         assert_eq!(package_system.excluded_packages_file, folder.join(super::EXCLUDED_PACKAGES_FILENAME));
@@ -27,4 +45,240 @@ mod construction {
         // everything else is already tested
         assert_eq!(package_system.name(), name);
     }
+
+    /// Errors in the package manager file point at the file and the line.
+    #[test]
+    fn from_folder_invalid() {
+        let temp_folder = TempFolder::new("from_folder_invalid");
+        let folder = temp_folder.path();
+        let package_manager_file = folder.join(PACKAGE_MANAGER_FILENAME);
+        fs::write(&package_manager_file, "[install_command]\ncommand = \"true\"\n\n[list_command\n").unwrap();
+        let error = PackageSystem::from_folder(folder).err().unwrap();
+        assert_eq!(error.exit_code(), crate::error::EXIT_CONFIG);
+        let message = error.to_string();
+        assert!(message.contains(&*package_manager_file.to_string_lossy()), "{}", message);
+        assert!(message.contains("line 4"), "{}", message);
+        // a missing file keeps the underlying error
+        let error = PackageSystem::from_folder(folder.join("missing")).err().unwrap();
+        assert_eq!(error.exit_code(), crate::error::EXIT_IO);
+    }
+}
+
+/// Tests `exclude()` and `reinclude()`.
+#[cfg(test)]
+mod exclusion {
+    use super::*;
+
+    /// The packages the list command reports as installed.
+    const INSTALLED: &str = "nano\ntrash-cli\nvim\n";
+
+    /// Excluded packages are recorded once and dropped from the packages file.
+    #[test]
+    fn exclude() {
+        let (mut package_system, _folder) = package_system("exclude", INSTALLED);
+        fs::write(&package_system.packages_file, "nano\nvim\n").unwrap();
+        package_system.exclude(["nano", "not-installed"]).unwrap();
+        package_system.exclude(["nano"]).unwrap();
+        assert_eq!(fs::read_to_string(&package_system.excluded_packages_file).unwrap(), "nano\nnot-installed\n");
+        assert_eq!(fs::read_to_string(&package_system.packages_file).unwrap(), "vim\n");
+    }
+
+    /// Reincluded packages are removed from the excluded packages file.
+    #[test]
+    fn reinclude() {
+        let (mut package_system, _folder) = package_system("reinclude", INSTALLED);
+        fs::write(&package_system.excluded_packages_file, "nano\ntrash-cli\n").unwrap();
+        package_system.reinclude(["trash-cli", "vim"]).unwrap();
+        assert_eq!(fs::read_to_string(&package_system.excluded_packages_file).unwrap(), "nano\n");
+    }
+
+    /// Patterns are recorded as written and drop every matching package from
+    /// the packages file.
+    #[test]
+    fn exclude_pattern() {
+        let (mut package_system, _folder) = package_system("exclude_pattern", INSTALLED);
+        fs::write(&package_system.packages_file, "nano\ntrash-cli\nvim\n").unwrap();
+        package_system.exclude(["trash-*", "re:n.*"]).unwrap();
+        assert_eq!(fs::read_to_string(&package_system.excluded_packages_file).unwrap(), "re:n.*\ntrash-*\n");
+        assert_eq!(fs::read_to_string(&package_system.packages_file).unwrap(), "vim\n");
+        assert!(package_system.exclude(["re:("]).is_err());
+    }
+
+    /// Tests `test_exclusion()`.
+    #[test]
+    fn test_exclusion() {
+        let (mut package_system, _folder) = package_system("test_exclusion", INSTALLED);
+        assert_eq!(package_system.test_exclusion("*i*").unwrap(), ["trash-cli", "vim"]);
+        assert!(package_system.test_exclusion("zsh").unwrap().is_empty());
+        // nothing is written
+        assert!(!package_system.excluded_packages_file.exists());
+    }
+}
+
+/// Tests `install()`.
+#[cfg(test)]
+mod installation {
+    use super::*;
+
+    /// The packages the list command reports as installed.
+    const INSTALLED: &str = "nano\nvim\n";
+
+    /// Only packages that aren't installed are passed to the install command.
+    #[test]
+    fn only_missing() {
+        let (mut package_system, _folder) = package_system("install_missing", INSTALLED);
+        fs::write(&package_system.packages_file, "nano\nzsh\nbash\n").unwrap();
+        package_system.install().unwrap();
+        let installed = fs::read_to_string(package_system.packages_file.with_file_name("installed.txt")).unwrap();
+        assert_eq!(installed, "bash zsh\n");
+    }
+
+    /// Packages from every layer used on this host are installed.
+    #[test]
+    fn layers() {
+        let (mut package_system, _folder) = package_system("install_layers", INSTALLED);
+        package_system.set_profiles(vec![String::from("laptop")]);
+        fs::write(&package_system.packages_file, "nano\n").unwrap();
+        fs::create_dir_all(package_system.layer_file("laptop").parent().unwrap()).unwrap();
+        fs::write(package_system.layer_file("laptop"), "tlp\n").unwrap();
+        fs::write(package_system.layer_file("work"), "slack\n").unwrap();
+        package_system.install().unwrap();
+        let installed = fs::read_to_string(package_system.packages_file.with_file_name("installed.txt")).unwrap();
+        assert_eq!(installed, "tlp\n");
+    }
+
+    /// The install command doesn't run when nothing is missing.
+    #[test]
+    fn nothing_missing() {
+        let (mut package_system, _folder) = package_system("install_nothing_missing", INSTALLED);
+        fs::write(&package_system.packages_file, "nano\nvim\n").unwrap();
+        package_system.install().unwrap();
+        assert!(!package_system.packages_file.with_file_name("installed.txt").exists());
+    }
+
+    /// A package system whose package manager file picks the `memory` backend.
+    #[test]
+    fn memory_backend() {
+        let temp_folder = TempFolder::new("memory_backend");
+        let folder = temp_folder.path();
+        fs::write(folder.join(PACKAGE_MANAGER_FILENAME), "type = \"memory\"\ninstalled = [\"git\"]\n").unwrap();
+        fs::write(folder.join(PACKAGES_FILENAME), "git\nvim\n").unwrap();
+        let mut package_system = PackageSystem::from_folder(folder).unwrap();
+        assert_eq!(package_system.status().unwrap().not_installed.iter().collect::<Vec<_>>(), ["vim"]);
+        package_system.install().unwrap();
+        assert!(package_system.status().unwrap().not_installed.is_empty());
+    }
+}
+
+/// Tests `upload()`.
+#[cfg(test)]
+mod upload {
+    use super::*;
+
+    /// Comments, sections and annotations survive an upload, and new packages
+    /// that aren't excluded are recorded in the host layer.
+    #[test]
+    fn keeps_notes() {
+        let (mut package_system, _folder) = package_system("keeps_notes", "git\nhtop\nnano\nnoto-fonts\ntrash-cli\n");
+        fs::write(&package_system.packages_file, "# base\nnano # for commit messages\nvim\n\n[fonts]\nnoto-fonts\n").unwrap();
+        fs::create_dir_all(package_system.layer_file("host").parent().unwrap()).unwrap();
+        fs::write(package_system.layer_file("host"), "# only here\nhtop\n").unwrap();
+        fs::write(&package_system.excluded_packages_file, "trash-*\n").unwrap();
+        package_system.upload().unwrap();
+        assert_eq!(fs::read_to_string(&package_system.packages_file).unwrap(), "# base\nnano # for commit messages\nvim\n\n[fonts]\nnoto-fonts\n");
+        assert_eq!(fs::read_to_string(package_system.layer_file("host")).unwrap(), "# only here\ngit\nhtop\n");
+        assert_eq!(fs::read_to_string(package_system.snapshot_file()).unwrap(), "git\nhtop\nnano\nnoto-fonts\n");
+    }
+
+    /// Only the changes since this host's last upload are applied, so packages
+    /// recorded by other hosts stay.
+    #[test]
+    fn merges() {
+        let (mut package_system, _folder) = package_system("merges", "git\nzsh\n");
+        // `vim` was recorded by another host and `nano` was uninstalled on this one
+        fs::write(&package_system.packages_file, "git\nnano\nvim\n").unwrap();
+        fs::create_dir_all(package_system.snapshot_file().parent().unwrap()).unwrap();
+        fs::write(package_system.snapshot_file(), "git\nnano\n").unwrap();
+        assert_eq!(package_system.status().unwrap().uninstalled_since_upload, [String::from("nano")].into());
+        package_system.set_upload_layer(COMMON_LAYER).unwrap();
+        package_system.upload().unwrap();
+        assert_eq!(fs::read_to_string(&package_system.packages_file).unwrap(), "git\nvim\nzsh\n");
+        assert_eq!(fs::read_to_string(package_system.snapshot_file()).unwrap(), "git\nzsh\n");
+    }
+
+    /// New packages go to the chosen layer and removed packages leave every layer.
+    #[test]
+    fn layers() {
+        let (mut package_system, _folder) = package_system("layers", "git\nsteam\n");
+        package_system.set_profiles(vec![String::from("gaming"), String::from("host")]);
+        assert_eq!(package_system.layers(), [COMMON_LAYER, "gaming", "host"]);
+        assert!(package_system.set_upload_layer("work").is_err());
+        fs::create_dir_all(package_system.layer_file("gaming").parent().unwrap()).unwrap();
+        fs::write(package_system.layer_file("gaming"), "lutris\n").unwrap();
+        fs::write(package_system.layer_file("work"), "slack\n").unwrap();
+        fs::create_dir_all(package_system.snapshot_file().parent().unwrap()).unwrap();
+        fs::write(package_system.snapshot_file(), "lutris\nslack\n").unwrap();
+        package_system.set_upload_layer("gaming").unwrap().upload().unwrap();
+        assert_eq!(fs::read_to_string(package_system.layer_file("gaming")).unwrap(), "git\nsteam\n");
+        // layers this host doesn't use are left alone
+        assert_eq!(fs::read_to_string(package_system.layer_file("work")).unwrap(), "slack\n");
+        assert_eq!(fs::read_to_string(&package_system.packages_file).unwrap(), "");
+        assert!(!package_system.layer_file("host").exists());
+    }
+
+    /// The dry run plans exactly what the upload does, so a package another
+    /// host dropped from a layer isn't recorded again.
+    #[test]
+    fn plan_matches() {
+        let (mut package_system, _folder) = package_system("plan_matches", "git\nnano\nzsh\n");
+        // another host dropped `nano`, and `vim` was uninstalled here
+        fs::write(&package_system.packages_file, "git\nvim\n").unwrap();
+        fs::create_dir_all(package_system.snapshot_file().parent().unwrap()).unwrap();
+        fs::write(package_system.snapshot_file(), "git\nnano\nvim\n").unwrap();
+        let plan = package_system.plan_upload(false).unwrap();
+        assert_eq!(plan.recorded, [(String::from("layers/host.txt"), ["zsh".to_string()].into())]);
+        assert_eq!(plan.removed, [(String::from(PACKAGES_FILENAME), ["vim".to_string()].into())]);
+        // `sync` installs `vim` again before uploading
+        assert!(package_system.plan_upload(true).unwrap().removed.is_empty());
+        // planning doesn't write anything
+        assert_eq!(fs::read_to_string(&package_system.packages_file).unwrap(), "git\nvim\n");
+        assert!(!package_system.layer_file("host").exists());
+
+        package_system.upload().unwrap();
+        assert_eq!(fs::read_to_string(&package_system.packages_file).unwrap(), "git\n");
+        assert_eq!(fs::read_to_string(package_system.layer_file("host")).unwrap(), "zsh\n");
+        assert_eq!(package_system.plan(Operation::Upload).unwrap(), "Would record (0)\nWould remove (0)");
+    }
+
+    /// A failing list command stops the upload before anything is written.
+    #[test]
+    fn list_fails() {
+        let (mut package_system, _folder) = package_system("list_fails", "");
+        package_system.package_manager = PackageManager::build(Command::new(""), Command::new("false"));
+        fs::write(&package_system.packages_file, "git\nnano\n").unwrap();
+        fs::create_dir_all(package_system.snapshot_file().parent().unwrap()).unwrap();
+        fs::write(package_system.snapshot_file(), "git\nnano\n").unwrap();
+        assert!(package_system.upload().is_err());
+        assert_eq!(fs::read_to_string(&package_system.packages_file).unwrap(), "git\nnano\n");
+        assert_eq!(fs::read_to_string(package_system.snapshot_file()).unwrap(), "git\nnano\n");
+    }
+}
+
+/// Tests `status()`.
+#[cfg(test)]
+mod status {
+    use super::*;
+
+    /// Missing package files are treated as empty and are not created.
+    #[test]
+    fn missing_files() {
+        let temp_folder = TempFolder::new("status");
+        let folder = temp_folder.path().join("does_not_exist");
+        let mut list_command = Command::new("printf");
+        list_command.arg("nano\n");
+        let mut package_system = PackageSystem::build(String::from("status"), &folder, PackageManager::build(Command::new(""), list_command));
+        let status = package_system.status().unwrap();
+        assert_eq!(status.not_recorded, [String::from("nano")].into());
+        assert!(!folder.exists());
+    }
 }
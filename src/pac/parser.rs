@@ -1,37 +1,143 @@
 //! Parser for the package manager configuration file.
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 use std::fs;
 use std::path::Path;
 
+use itertools::Itertools;
+use serde_derive::Deserialize;
+
 use super::package_system::*;
+use super::privilege::Privilege;
+use super::template::TemplateContext;
+use crate::Error;
+
+/// The file in a package systems folder with settings for every package system.
+pub const SETTINGS_FILENAME: &str = "settings.toml";
+
+/// Settings for every package system, read from `settings.toml`.
+#[derive(Deserialize, Default, Debug)]
+#[serde(deny_unknown_fields)]
+pub struct GlobalSettings {
+    /// How to run commands as root, unless a package system overrides it.
+    #[serde(default)]
+    pub privilege: Option<Privilege>,
+    /// The profiles each host uses, keyed by hostname.
+    ///
+    /// ## Example
+    /// <pre>
+    /// [hosts]
+    /// thinkpad = ["laptop", "work"]
+    /// tower = ["gaming"]
+    /// </pre>
+    #[serde(default)]
+    pub hosts: BTreeMap<String, Vec<String>>,
+}
+impl GlobalSettings {
+    /// Read the settings file in `folder`. A missing file means default settings.
+    ///
+    /// # Errors
+    /// - The settings file exists but cannot be read or is invalid.
+    //# INTEGRATION TESTED
+    pub fn from_folder(folder: impl AsRef<Path>) -> Result<Self, Error> {
+        let path = folder.as_ref().join(SETTINGS_FILENAME);
+        if !path.exists() {
+            return Ok(Self::default());
+        }
+        let contents = fs::read_to_string(&path).map_err(|e| Error::io("read", &path, e))?;
+        toml::from_str(&contents).map_err(|e| Error::from(e).in_file(&path))
+    }
+
+    /// Apply these settings to `package_system` on the host `hostname`.
+    /// Settings the package system overrides are skipped.
+    pub fn apply(&self, package_system: &mut PackageSystem, hostname: &str) {
+        if let Some(privilege) = self.privilege
+            && package_system.settings().privilege.is_none()
+        {
+            package_system.package_manager_mut().set_privilege(privilege);
+        }
+        if let Some(profiles) = self.hosts.get(hostname) {
+            package_system.set_profiles(profiles.clone());
+        }
+    }
+}
 
 /// Read a list of `PackageSystem`s from subfolders containing **TOML** files in
-/// `folder`.
+/// `folder`. User template variables are read from `folder`'s `variables.toml`
+/// and global settings from its `settings.toml`.
 ///
 /// # Errors
 /// - Failed to list subfolders in `folder`.
+/// - Any errors from `TemplateContext::detect_with_variables()`.
+/// - Any errors from `GlobalSettings::from_folder()`.
 /// - Failed to create the package manager because of an invalid file format.
 //# INTEGRATION TESTED
-pub fn package_systems_from_folder(folder: impl AsRef<Path>) -> Result<HashMap<String, PackageSystem>, String> {
+pub fn package_systems_from_folder(folder: impl AsRef<Path>) -> Result<HashMap<String, PackageSystem>, Error> {
     let mut package_systems: HashMap<String, PackageSystem> = HashMap::new();
+    let context = TemplateContext::detect_with_variables(&folder)?;
+    let global_settings = GlobalSettings::from_folder(&folder)?;
     // get an iterator of paths in the folder
-    let Ok(package_system_paths) = fs::read_dir(folder.as_ref()) else {
-        return Err("Invalid package systems folder".to_string());
-    };
+    let package_system_paths = fs::read_dir(folder.as_ref()).map_err(|e| Error::io("read package systems folder", folder.as_ref(), e))?;
     for package_system_path in package_system_paths {
         // get the actual path
-        let package_system_path = if let Ok(temp) = package_system_path {
-            temp.path()
-        } else {
-            // this error probably never runs
-            return Err("Invalid package system folder detected".to_string());
-        };
+        let package_system_path = package_system_path
+            .map_err(|e| Error::io("read package systems folder", folder.as_ref(), e))?
+            .path();
         // only operate on directories
         if package_system_path.is_dir() {
             // try to create a package system from the current folder
-            let package_system = PackageSystem::from_folder(&package_system_path)?;
+            let mut package_system = PackageSystem::from_folder_with_context(&package_system_path, &context)?;
+            global_settings.apply(&mut package_system, &context.hostname);
             package_systems.insert(package_system.name().to_owned(), package_system);
         }
     }
     Ok(package_systems)
 }
+
+/// Pick the name of the package system to use when none is specified. The
+/// package system marked with `default = true` wins. Otherwise, the package
+/// system whose programs are all available on this machine is detected.
+///
+/// # Errors
+/// - There are no package systems.
+/// - More than one package system is marked as the default.
+/// - Zero or multiple package systems were detected.
+//# INTEGRATION TESTED
+pub fn default_package_system_name(package_systems: &HashMap<String, PackageSystem>) -> Result<String, Error> {
+    if package_systems.is_empty() {
+        return Err(Error::Usage(String::from("No package systems found")));
+    }
+    // a lone package system is always the default
+    if package_systems.len() == 1 {
+        return Ok(package_systems.keys().next().unwrap().to_owned());
+    }
+    // prefer package systems explicitly marked as the default
+    let marked = package_systems
+        .values()
+        .filter(|system| system.settings().default)
+        .map(PackageSystem::name)
+        .sorted()
+        .collect::<Vec<_>>();
+    match marked.as_slice() {
+        [name] => return Ok(name.to_string()),
+        [] => {}
+        _ => return Err(Error::config(format!("Multiple package systems are marked as the default: {}", marked.iter().join(", ")))),
+    }
+    // fall back to detecting the package systems available on this machine
+    let detected = package_systems
+        .values()
+        .filter(|system| system.package_manager().is_available())
+        .map(PackageSystem::name)
+        .sorted()
+        .collect::<Vec<_>>();
+    match detected.as_slice() {
+        [name] => Ok(name.to_string()),
+        [] => Err(Error::Usage(format!(
+            "Could not detect a package system on this machine. Candidates: {}. Specify one with `--name` or set `default = true`",
+            package_systems.keys().sorted().join(", ")
+        ))),
+        _ => Err(Error::Usage(format!(
+            "Multiple package systems detected: {}. Specify one with `--name` or set `default = true`",
+            detected.iter().join(", ")
+        ))),
+    }
+}
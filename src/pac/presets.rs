@@ -0,0 +1,38 @@
+//! Built-in package manager files for common package managers.
+use crate::Error;
+
+#[cfg(test)]
+mod tests;
+
+/// Each preset's name and package manager file contents.
+pub const PRESETS: [(&str, &str); 12] = [
+    ("apt", include_str!("../../templates/presets/apt.toml")),
+    ("brew", include_str!("../../templates/presets/brew.toml")),
+    ("cargo", include_str!("../../templates/presets/cargo.toml")),
+    ("dnf", include_str!("../../templates/presets/dnf.toml")),
+    ("flatpak", include_str!("../../templates/presets/flatpak.toml")),
+    ("npm", include_str!("../../templates/presets/npm.toml")),
+    ("pacman", include_str!("../../templates/presets/pacman.toml")),
+    ("paru", include_str!("../../templates/presets/paru.toml")),
+    ("pipx", include_str!("../../templates/presets/pipx.toml")),
+    ("snap", include_str!("../../templates/presets/snap.toml")),
+    ("yay", include_str!("../../templates/presets/yay.toml")),
+    ("zypper", include_str!("../../templates/presets/zypper.toml")),
+];
+
+/// Get the names of all presets.
+pub fn preset_names() -> impl Iterator<Item = &'static str> {
+    PRESETS.iter().map(|(name, _)| *name)
+}
+
+/// Get the package manager file contents of the preset called `name`.
+///
+/// # Errors
+/// - There is no preset called `name`.
+pub fn preset(name: &str) -> Result<&'static str, Error> {
+    PRESETS
+        .iter()
+        .find(|(preset_name, _)| *preset_name == name)
+        .map(|(_, contents)| *contents)
+        .ok_or_else(|| Error::Usage(format!("Unknown preset `{}`. Available: {}", name, preset_names().collect::<Vec<_>>().join(", "))))
+}
@@ -0,0 +1,17 @@
+use super::*;
+use crate::pac::PackageManager;
+
+/// Make sure every preset is a valid package manager file.
+#[test]
+fn all_valid() {
+    for (name, contents) in PRESETS {
+        assert!(PackageManager::from_toml_string(contents, "").is_ok(), "Invalid preset `{}`", name);
+    }
+}
+
+/// Tests `preset()`.
+#[test]
+fn lookup() {
+    assert!(preset("apt").unwrap().contains("apt-get"));
+    assert!(preset("does-not-exist").unwrap_err().to_string().contains("apt, brew"));
+}
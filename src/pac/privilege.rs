@@ -0,0 +1,112 @@
+//! Running commands that need root with `sudo`, `doas` or `run0`.
+use std::ffi::{OsStr, OsString};
+use std::process::Command;
+
+use serde_derive::Deserialize;
+
+use crate::Error;
+
+#[cfg(test)]
+mod tests;
+
+/// How to run commands that need root.
+#[derive(Deserialize, Clone, Copy, Debug, Default, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Privilege {
+    /// Use the first of `sudo`, `doas` and `run0` that is on the `PATH`.
+    #[default]
+    Auto,
+    Sudo,
+    Doas,
+    Run0,
+    /// Run the commands as they are, such as in a container running as root.
+    None,
+}
+impl Privilege {
+    /// Get the program that runs another program as root, or `None` if no
+    /// escalation is needed or possible.
+    //# UNIT TESTED
+    pub fn program(self) -> Option<&'static str> {
+        match self {
+            Privilege::Auto => ["sudo", "doas", "run0"]
+                .into_iter()
+                .find(|program| utils::find_program(program).is_some()),
+            Privilege::Sudo => Some("sudo"),
+            Privilege::Doas => Some("doas"),
+            Privilege::Run0 => Some("run0"),
+            Privilege::None => None,
+        }
+    }
+
+    /// Wrap `command` so it runs as root. The command is returned unchanged if
+    /// this process is already root or there is no escalation program.
+    //# UNIT TESTED
+    pub fn wrap(self, command: Command) -> Command {
+        match self.program().filter(|_| !is_root()) {
+            Some(program) => wrap_with(program, &command),
+            None => command,
+        }
+    }
+
+    /// Ask for credentials up front so they are cached for the rest of a long
+    /// run. Does nothing if no escalation is needed or it can't be cached.
+    ///
+    /// # Errors
+    /// - The credentials could not be refreshed.
+    pub fn refresh_credentials(self) -> Result<(), Error> {
+        let (program, args): (_, &[&str]) = match self.program().filter(|_| !is_root()) {
+            Some(program @ "sudo") => (program, &["-v"]),
+            Some(program @ "doas") => (program, &["true"]),
+            _ => return Ok(()),
+        };
+        match Command::new(program).args(args).status() {
+            Ok(status) if status.success() => Ok(()),
+            Ok(_) => Err(Error::command(format!("Failed to refresh `{}` credentials", program))),
+            Err(e) => Err(Error::Command {
+                message: format!("Failed to run `{}`", program),
+                source: Some(e),
+            }),
+        }
+    }
+}
+
+/// Helper function to wrap `command` in the escalation `program`. The
+/// escalation programs reset the environment, so `command`'s environment
+/// variables are passed as arguments: `--setenv` for `run0`, which also starts
+/// from a clean environment, and through `env` for the others.
+//# UNIT TESTED
+fn wrap_with(program: &str, command: &Command) -> Command {
+    let mut wrapped = Command::new(program);
+    if program == "run0" {
+        for (key, value) in command.get_envs() {
+            if let Some(value) = value {
+                let mut arg = OsString::from("--setenv=");
+                arg.extend([key, OsStr::new("="), value]);
+                wrapped.arg(arg);
+            }
+        }
+    } else if command.get_envs().next().is_some() {
+        wrapped.arg("env");
+        for (key, value) in command.get_envs() {
+            match value {
+                Some(value) => {
+                    let mut arg = key.to_os_string();
+                    arg.extend([OsStr::new("="), value]);
+                    wrapped.arg(arg)
+                }
+                None => wrapped.arg("-u").arg(key),
+            };
+        }
+    }
+    wrapped.arg(command.get_program()).args(command.get_args());
+    if let Some(dir) = command.get_current_dir() {
+        wrapped.current_dir(dir);
+    }
+    wrapped
+}
+
+/// Whether this process is running as root.
+pub fn is_root() -> bool {
+    // SAFETY: `geteuid()` has no preconditions and can't fail
+    unsafe { libc::geteuid() == 0 }
+}
@@ -0,0 +1,40 @@
+use super::*;
+
+/// Tests `program()`.
+#[test]
+fn program() {
+    assert_eq!(Privilege::Doas.program(), Some("doas"));
+    assert_eq!(Privilege::None.program(), None);
+}
+
+/// Tests `wrap()`.
+#[test]
+fn wrap() {
+    let mut command = Command::new("pacman");
+    command.args(["-S", "--needed"]).env("LANG", "C");
+    let wrapped = Privilege::Doas.wrap(command);
+    if is_root() {
+        assert_eq!(wrapped.get_program(), "pacman");
+    } else {
+        assert_eq!(wrapped.get_program(), "doas");
+        assert_eq!(wrapped.get_args().collect::<Vec<_>>(), ["pacman", "-S", "--needed"]);
+    }
+    // no escalation leaves the command alone
+    assert_eq!(Privilege::None.wrap(Command::new("pacman")).get_program(), "pacman");
+}
+
+/// Tests `wrap_with()`.
+#[test]
+fn wrap_with_env() {
+    let mut command = Command::new("pacman");
+    command.args(["-S", "--needed"]).env("LANG", "C").env_remove("PAGER");
+    let wrapped = wrap_with("sudo", &command);
+    assert_eq!(wrapped.get_program(), "sudo");
+    assert_eq!(wrapped.get_args().collect::<Vec<_>>(), ["env", "LANG=C", "-u", "PAGER", "pacman", "-S", "--needed"]);
+    // the variables reach the command instead of being reset by `sudo`
+    assert_eq!(wrapped.get_envs().count(), 0);
+    let wrapped = wrap_with("run0", &command);
+    assert_eq!(wrapped.get_args().collect::<Vec<_>>(), ["--setenv=LANG=C", "pacman", "-S", "--needed"]);
+    // without variables, `env` isn't needed
+    assert_eq!(wrap_with("doas", &Command::new("pacman")).get_args().collect::<Vec<_>>(), ["pacman"]);
+}
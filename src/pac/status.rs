@@ -0,0 +1,99 @@
+//! Differences between the recorded and installed packages of a package system.
+use std::collections::{BTreeSet, HashSet};
+use std::fmt;
+
+use itertools::Itertools;
+use serde_derive::Serialize;
+
+#[cfg(test)]
+mod tests;
+
+/// How a package system's recorded packages differ from what is installed.
+/// The recorded packages are those of every layer used on this host: the
+/// packages file (**installed-packages.txt**) and **layers/<profile>.txt** or
+/// **layers/<hostname>.txt**.
+#[derive(Serialize, Debug, Default, PartialEq)]
+pub struct Status {
+    /// Packages in any layer used on this host that are not installed.
+    pub not_installed: BTreeSet<String>,
+    /// Installed packages that are neither in a layer used on this host nor
+    /// excluded.
+    pub not_recorded: BTreeSet<String>,
+    /// Excluded packages that are installed.
+    pub excluded_installed: BTreeSet<String>,
+    /// Recorded packages that were installed on this host's last upload but
+    /// are not installed anymore.
+    pub uninstalled_since_upload: BTreeSet<String>,
+}
+impl Status {
+    /// Compare the packages `recorded` in every layer used on this host, the
+    /// `excluded` and `installed` package lists, and this host's `snapshot` of
+    /// its last upload.
+    //# UNIT TESTED
+    pub fn new(recorded: &HashSet<String>, excluded: &HashSet<String>, installed: &HashSet<String>, snapshot: &HashSet<String>) -> Self {
+        Status {
+            not_installed: recorded.difference(installed).cloned().collect(),
+            not_recorded: installed
+                .iter()
+                .filter(|package| !recorded.contains(*package) && !excluded.contains(*package))
+                .cloned()
+                .collect(),
+            excluded_installed: excluded.intersection(installed).cloned().collect(),
+            uninstalled_since_upload: snapshot
+                .iter()
+                .filter(|package| recorded.contains(*package) && !installed.contains(*package))
+                .cloned()
+                .collect(),
+        }
+    }
+
+    /// Whether the recorded and installed packages match.
+    pub fn is_clean(&self) -> bool {
+        self.not_installed.is_empty() && self.not_recorded.is_empty()
+    }
+
+    /// Describe what `install()` would install without running it.
+    //# UNIT TESTED
+    pub fn install_plan(&self) -> String {
+        describe("Would install", &self.not_installed)
+    }
+}
+impl fmt::Display for Status {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        writeln!(f, "{}", describe("Recorded but not installed", &self.not_installed))?;
+        writeln!(f, "{}", describe("Installed but not recorded", &self.not_recorded))?;
+        write!(f, "{}", describe("Excluded but installed", &self.excluded_installed))
+    }
+}
+
+/// What `upload()` would change in the layers used on this host. Layers are
+/// named by their package file, relative to the package system folder.
+#[derive(Debug, Default, PartialEq)]
+pub struct UploadPlan {
+    /// The packages that would be added to each layer.
+    pub recorded: Vec<(String, BTreeSet<String>)>,
+    /// The packages that would be removed from each layer.
+    pub removed: Vec<(String, BTreeSet<String>)>,
+}
+impl fmt::Display for UploadPlan {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut lines = Vec::new();
+        for (heading, preposition, changes) in [("Would record", "in", &self.recorded), ("Would remove", "from", &self.removed)] {
+            if changes.is_empty() {
+                lines.push(describe(heading, &BTreeSet::new()));
+            }
+            for (file, packages) in changes {
+                lines.push(describe(&format!("{} {} {}", heading, preposition, file), packages));
+            }
+        }
+        write!(f, "{}", lines.join("\n"))
+    }
+}
+
+/// Helper function to format a heading followed by an indented list of `packages`.
+fn describe(heading: &str, packages: &BTreeSet<String>) -> String {
+    if packages.is_empty() {
+        return format!("{} (0)", heading);
+    }
+    format!("{} ({}):\n{}", heading, packages.len(), packages.iter().map(|package| format!("  {}", package)).join("\n"))
+}
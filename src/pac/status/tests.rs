@@ -0,0 +1,46 @@
+use super::*;
+
+/// Build a `HashSet` of package names.
+fn packages(names: &[&str]) -> HashSet<String> {
+    names.iter().map(|name| name.to_string()).collect()
+}
+
+/// Build a status where `a` and `f` are not installed, `d` is not recorded,
+/// `e` is excluded but installed and `f` was uninstalled since the last upload.
+fn status() -> Status {
+    Status::new(&packages(&["a", "b", "f"]), &packages(&["c", "e"]), &packages(&["b", "d", "e"]), &packages(&["b", "f"]))
+}
+
+/// Tests `Status::new()`.
+#[test]
+fn new() {
+    let status = status();
+    assert_eq!(status.not_installed, ["a".to_string(), "f".to_string()].into());
+    assert_eq!(status.not_recorded, ["d".to_string()].into());
+    assert_eq!(status.excluded_installed, ["e".to_string()].into());
+    assert_eq!(status.uninstalled_since_upload, ["f".to_string()].into());
+    assert!(!status.is_clean());
+}
+
+/// Tests `Status::install_plan()`.
+#[test]
+fn install_plan() {
+    assert_eq!(status().install_plan(), "Would install (2):\n  a\n  f");
+}
+
+/// Tests displaying an `UploadPlan`.
+#[test]
+fn upload_plan() {
+    assert_eq!(UploadPlan::default().to_string(), "Would record (0)\nWould remove (0)");
+    let plan = UploadPlan {
+        recorded: vec![(String::from("layers/host.txt"), ["d".to_string()].into())],
+        removed: vec![
+            (String::from("installed-packages.txt"), ["f".to_string()].into()),
+            (String::from("layers/host.txt"), ["g".to_string()].into()),
+        ],
+    };
+    assert_eq!(
+        plan.to_string(),
+        "Would record in layers/host.txt (1):\n  d\nWould remove from installed-packages.txt (1):\n  f\nWould remove from layers/host.txt (1):\n  g"
+    );
+}
@@ -0,0 +1,97 @@
+//! Rendering package manager files as `minijinja` templates.
+use std::collections::BTreeMap;
+use std::path::Path;
+use std::{env, fs};
+
+use minijinja::Environment;
+use serde_derive::Serialize;
+
+use crate::Error;
+
+#[cfg(test)]
+mod tests;
+
+/// The file in a package systems folder that defines user variables.
+pub const VARIABLES_FILENAME: &str = "variables.toml";
+
+/// Variables available to package manager file templates.
+///
+/// # Template Variables
+/// - `hostname` - The machine's hostname.
+/// - `distro` - The `ID` from **/etc/os-release**, such as `arch` or `ubuntu`.
+/// - `arch` - The CPU architecture, such as `x86_64`.
+/// - `vars` - User variables from `variables.toml`.
+/// - `env` - The environment variables.
+#[derive(Serialize, Default, Debug, Clone)]
+pub struct TemplateContext {
+    pub hostname: String,
+    pub distro: String,
+    pub arch: String,
+    pub vars: toml::Table,
+    pub env: BTreeMap<String, String>,
+}
+impl TemplateContext {
+    /// Build a context describing the current machine, without user variables.
+    pub fn detect() -> Self {
+        TemplateContext {
+            hostname: detect_hostname(),
+            distro: detect_distro(),
+            arch: env::consts::ARCH.to_string(),
+            vars: toml::Table::new(),
+            env: env::vars().collect(),
+        }
+    }
+
+    /// Build a context describing the current machine, with user variables
+    /// from the variables file in `folder` if there is one.
+    ///
+    /// # Errors
+    /// - The variables file exists but cannot be read or is invalid.
+    //# UNIT TESTED
+    pub fn detect_with_variables(folder: impl AsRef<Path>) -> Result<Self, Error> {
+        let mut context = Self::detect();
+        let path = folder.as_ref().join(VARIABLES_FILENAME);
+        if path.exists() {
+            let contents = fs::read_to_string(&path).map_err(|e| Error::io("read", &path, e))?;
+            context.vars = contents.parse().map_err(|e| Error::from(e).in_file(&path))?;
+        }
+        Ok(context)
+    }
+
+    /// Render `template` with this context.
+    ///
+    /// # Errors
+    /// - The template is invalid.
+    //# UNIT TESTED
+    pub fn render(&self, template: &str) -> Result<String, Error> {
+        let mut environment = Environment::new();
+        // keep the file's trailing newline
+        environment.set_keep_trailing_newline(true);
+        environment
+            .render_str(template, self)
+            .map_err(|e| Error::config(format!("Failed to render template: {}", e)))
+    }
+}
+
+/// Helper function to get the hostname, or an empty string if it's unknown.
+pub(crate) fn detect_hostname() -> String {
+    ["/proc/sys/kernel/hostname", "/etc/hostname"]
+        .iter()
+        .find_map(|path| fs::read_to_string(path).ok())
+        .map(|hostname| hostname.trim().to_string())
+        .unwrap_or_default()
+}
+
+/// Helper function to get the distro ID from **/etc/os-release**, or an empty
+/// string if it's unknown.
+fn detect_distro() -> String {
+    fs::read_to_string("/etc/os-release")
+        .ok()
+        .and_then(|contents| {
+            contents
+                .lines()
+                .find_map(|line| line.strip_prefix("ID="))
+                .map(|id| id.trim_matches('"').to_string())
+        })
+        .unwrap_or_default()
+}
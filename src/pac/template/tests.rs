@@ -0,0 +1,64 @@
+use super::*;
+
+/// Build a context for a made-up machine.
+fn context() -> TemplateContext {
+    TemplateContext {
+        hostname: String::from("laptop"),
+        distro: String::from("arch"),
+        arch: String::from("x86_64"),
+        vars: "unattended = true\naur_helper = \"paru\"".parse().unwrap(),
+        env: BTreeMap::new(),
+    }
+}
+
+/// Tests `render()` with machine and user variables.
+#[test]
+fn render() {
+    let template = "command = \"{{ vars.aur_helper }}\"\nargs = [\"-S\"{% if vars.unattended %}, \"--noconfirm\"{% endif %}]\n# {{ hostname }} {{ distro }} {{ arch }}\n";
+    assert_eq!(context().render(template).unwrap(), "command = \"paru\"\nargs = [\"-S\", \"--noconfirm\"]\n# laptop arch x86_64\n");
+}
+
+/// Tests `render()` with an invalid template.
+#[test]
+fn invalid() {
+    assert!(context().render("{% if %}").is_err());
+}
+
+/// Tests `detect_with_variables()`.
+#[cfg(test)]
+mod variables_file {
+    use super::*;
+
+    /// The folder holding the variables file fixtures.
+    fn fixtures() -> &'static Path {
+        Path::new("test_files/pac/template")
+    }
+
+    /// The variables file is read into `vars`.
+    #[test]
+    fn valid() {
+        let context = TemplateContext::detect_with_variables(fixtures().join("valid")).unwrap();
+        assert_eq!(context.vars["aur_helper"].as_str(), Some("paru"));
+        assert_eq!(context.vars["unattended"].as_bool(), Some(true));
+        assert_eq!(context.vars["mirror"]["country"].as_str(), Some("DE"));
+        assert_eq!(context.arch, env::consts::ARCH);
+        assert_eq!(context.render("{{ vars.mirror.country }}").unwrap(), "DE");
+    }
+
+    /// A missing variables file means no user variables.
+    #[test]
+    fn missing() {
+        let context = TemplateContext::detect_with_variables(fixtures().join("does-not-exist")).unwrap();
+        assert!(context.vars.is_empty());
+    }
+
+    /// An invalid variables file is an error that names the file.
+    #[test]
+    fn invalid() {
+        let error = TemplateContext::detect_with_variables(fixtures().join("invalid")).err().unwrap();
+        assert_eq!(error.exit_code(), crate::error::EXIT_CONFIG);
+        let message = error.to_string();
+        assert!(message.contains("invalid/variables.toml"), "{}", message);
+        assert!(message.contains("line 1"), "{}", message);
+    }
+}
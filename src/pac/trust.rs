@@ -0,0 +1,165 @@
+//! Approving the commands in package manager files before they run.
+//!
+//! Package system folders are usually synced between machines, so a pulled
+//! package manager file can change the commands `pac` runs. The hash of every
+//! approved set of commands is recorded in local state outside the dotfiles,
+//! and commands that don't match their approval are shown to the user before
+//! anything runs.
+use std::collections::BTreeMap;
+use std::fs;
+use std::io::ErrorKind;
+use std::path::{Path, PathBuf};
+use std::sync::LazyLock;
+
+use serde_derive::{Deserialize, Serialize};
+use sha2::{Digest, Sha256};
+use similar::{ChangeTag, TextDiff};
+
+use super::package_system::PackageSystem;
+use crate::{Error, STATE_DIR};
+
+#[cfg(test)]
+mod tests;
+
+/// The file in the state folder that records approved commands.
+pub const TRUST_FILENAME: &str = "trusted-commands.toml";
+/// Keys in a package manager file that decide what runs: the backend type and
+/// the tables that hold a command.
+const COMMAND_KEYS: [&str; 5] = ["type", "install_command", "list_command", "check_command", "bootstrap_command"];
+
+/// Where approved commands are recorded.
+pub static TRUST_FILE: LazyLock<PathBuf> = LazyLock::new(|| STATE_DIR.join(TRUST_FILENAME));
+
+/// The approved commands of one package system folder.
+#[derive(Serialize, Deserialize, Debug, Clone, PartialEq)]
+struct Approval {
+    /// The SHA-256 hash of `commands`.
+    hash: String,
+    /// The approved commands, kept to show what changed.
+    commands: String,
+}
+
+/// Whether a package system's commands have been approved.
+#[derive(Debug, PartialEq)]
+pub enum Trust {
+    /// The commands match their approval.
+    Trusted,
+    /// The package system's commands have never been approved.
+    New,
+    /// The commands changed since they were approved.
+    Changed {
+        /// The commands that were approved.
+        approved: String,
+    },
+}
+
+/// The approved commands of every package system folder, keyed by the
+/// folder's path.
+#[derive(Debug, Default)]
+pub struct TrustStore {
+    path: PathBuf,
+    approvals: BTreeMap<String, Approval>,
+}
+impl TrustStore {
+    /// Read the approvals from `path`. A missing file has no approvals.
+    ///
+    /// # Errors
+    /// - The file exists but cannot be read.
+    /// - The file is invalid.
+    //# UNIT TESTED
+    pub fn load(path: impl AsRef<Path>) -> Result<Self, Error> {
+        let path = path.as_ref();
+        let approvals = match fs::read_to_string(path) {
+            Ok(contents) => toml::from_str(&contents).map_err(|e| Error::from(e).in_file(path))?,
+            Err(e) if e.kind() == ErrorKind::NotFound => BTreeMap::new(),
+            Err(e) => return Err(Error::io("read", path, e)),
+        };
+        Ok(TrustStore {
+            path: path.to_path_buf(),
+            approvals,
+        })
+    }
+
+    /// Write the approvals back to the file they were read from, creating its
+    /// folder if needed.
+    ///
+    /// # Errors
+    /// - The file or its folder cannot be written.
+    //# UNIT TESTED
+    pub fn save(&self) -> Result<(), Error> {
+        if let Some(parent) = self.path.parent() {
+            fs::create_dir_all(parent).map_err(|e| Error::io("create", parent, e))?;
+        }
+        let contents = toml::to_string(&self.approvals).expect("approvals are always serializable");
+        fs::write(&self.path, contents).map_err(|e| Error::io("write", &self.path, e))
+    }
+
+    /// Compare `package_system`'s commands with their approval.
+    //# UNIT TESTED
+    pub fn verify(&self, package_system: &PackageSystem) -> Trust {
+        match self.approvals.get(&approval_key(package_system)) {
+            Some(approval) if approval.hash == hash(package_system.commands()) => Trust::Trusted,
+            Some(approval) => Trust::Changed {
+                approved: approval.commands.clone(),
+            },
+            None => Trust::New,
+        }
+    }
+
+    /// Approve `package_system`'s current commands.
+    //# UNIT TESTED
+    pub fn approve(&mut self, package_system: &PackageSystem) {
+        let commands = package_system.commands().to_string();
+        self.approvals.insert(
+            approval_key(package_system),
+            Approval {
+                hash: hash(&commands),
+                commands,
+            },
+        );
+    }
+}
+
+/// Get the backend type and commands in the rendered package manager file
+/// `contents` as **TOML**, without the keys that don't run anything.
+///
+/// # Errors
+/// - `contents` is not valid **TOML**.
+//# UNIT TESTED
+pub fn command_tables(contents: &str) -> Result<String, Error> {
+    let mut table = contents.parse::<toml::Table>()?;
+    table.retain(|key, _| COMMAND_KEYS.contains(&key));
+    Ok(toml::to_string(&table).expect("a table is always serializable"))
+}
+
+/// Show how `new` differs from `old`, line by line, with `+` and `-` markers.
+//# UNIT TESTED
+pub fn diff(old: &str, new: &str) -> String {
+    TextDiff::from_lines(old, new)
+        .iter_all_changes()
+        .map(|change| {
+            let sign = match change.tag() {
+                ChangeTag::Delete => "-",
+                ChangeTag::Insert => "+",
+                ChangeTag::Equal => " ",
+            };
+            format!("{}{}", sign, change.to_string_lossy().trim_end_matches('\n'))
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Helper function to get the key of `package_system`'s approval, its
+/// folder's full path.
+fn approval_key(package_system: &PackageSystem) -> String {
+    let folder = package_system.folder();
+    fs::canonicalize(folder)
+        .unwrap_or_else(|_| folder.to_path_buf())
+        .to_string_lossy()
+        .to_string()
+}
+
+/// Helper function to hash `commands` as a hex string.
+fn hash(commands: &str) -> String {
+    Sha256::digest(commands.as_bytes()).iter().map(|byte| format!("{:02x}", byte)).collect()
+}
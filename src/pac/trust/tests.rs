@@ -0,0 +1,93 @@
+use test_utils::TempFolder;
+
+use super::*;
+use crate::pac::PACKAGE_MANAGER_FILENAME;
+
+/// Tests `command_tables()`.
+#[cfg(test)]
+mod commands {
+    use super::*;
+
+    /// Only the command tables are kept.
+    #[test]
+    fn command_tables_only() {
+        let contents = "default = true\nrequires = [\"pacman\"]\n\n[install_command]\ncommand = \"yay\"\nargs = [\"-S\"]\n\n[list_command]\ncommand = \"yay\"\n\n[list_parser]\nmode = \"lines\"\n";
+        let commands = command_tables(contents).unwrap();
+        assert!(commands.contains("[install_command]"), "{}", commands);
+        assert!(commands.contains("[list_command]"), "{}", commands);
+        assert!(!commands.contains("default"), "{}", commands);
+        assert!(!commands.contains("list_parser"), "{}", commands);
+        // formatting and settings don't change the commands
+        let reformatted = "[list_command]\ncommand = 'yay'\n\n[install_command]\nargs = ['-S']\ncommand = 'yay'\n";
+        assert_eq!(command_tables(reformatted).unwrap(), commands);
+        assert!(command_tables("[install_command\n").is_err());
+    }
+
+    /// Tests `diff()`.
+    #[test]
+    fn diff_lines() {
+        assert_eq!(
+            diff("command = \"yay\"\nargs = [\"-S\"]\n", "command = \"yay\"\nargs = [\"-S\", \"--noconfirm\"]\n"),
+            " command = \"yay\"\n-args = [\"-S\"]\n+args = [\"-S\", \"--noconfirm\"]"
+        );
+        assert_eq!(diff("", "a\n"), "+a");
+    }
+}
+
+/// Tests `TrustStore`.
+#[cfg(test)]
+mod store {
+    use super::*;
+
+    /// Write a package manager file to `folder` whose install command runs
+    /// `program`, and load the package system.
+    fn package_system(folder: &TempFolder, program: &str) -> PackageSystem {
+        let folder = folder.path();
+        fs::write(
+            folder.join(PACKAGE_MANAGER_FILENAME),
+            format!("[install_command]\ncommand = \"{}\"\n\n[list_command]\ncommand = \"true\"\n", program),
+        )
+        .unwrap();
+        PackageSystem::from_folder(folder).unwrap()
+    }
+
+    /// Commands are new, then trusted once approved, then changed once the
+    /// package manager file is edited.
+    #[test]
+    fn verify() {
+        let folder = TempFolder::new("verify");
+        let mut trust = TrustStore::default();
+        let original = package_system(&folder, "true");
+        assert_eq!(trust.verify(&original), Trust::New);
+        trust.approve(&original);
+        assert_eq!(trust.verify(&original), Trust::Trusted);
+
+        let edited = package_system(&folder, "curl");
+        assert_eq!(
+            trust.verify(&edited),
+            Trust::Changed {
+                approved: original.commands().to_string()
+            }
+        );
+        trust.approve(&edited);
+        assert_eq!(trust.verify(&edited), Trust::Trusted);
+    }
+
+    /// Approvals survive being saved and loaded, and a missing file has none.
+    #[test]
+    fn save_and_load() {
+        let folder = TempFolder::new("save_and_load");
+        // the state folder doesn't exist yet
+        let path = folder.path().with_file_name("state").join(TRUST_FILENAME);
+        let package_system = package_system(&folder, "true");
+
+        let mut trust = TrustStore::load(&path).unwrap();
+        assert_eq!(trust.verify(&package_system), Trust::New);
+        trust.approve(&package_system);
+        trust.save().unwrap();
+        assert_eq!(TrustStore::load(&path).unwrap().verify(&package_system), Trust::Trusted);
+
+        fs::write(&path, "not toml").unwrap();
+        assert_eq!(TrustStore::load(&path).err().unwrap().exit_code(), crate::error::EXIT_CONFIG);
+    }
+}
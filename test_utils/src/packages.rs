@@ -1,48 +1,156 @@
-//! Utilities for interacting with the system's package manager during testing.
-//! This assumes you are testing on ArchLinux using pacman.
+//! A fake package manager for testing, so tests don't need a real package
+//! manager or root.
+//!
+//! The fake is a shell script in its own temporary folder. It keeps its
+//! installed packages in a state file next to itself and records every
+//! invocation, so each test gets an isolated package manager and tests can run
+//! in parallel.
 
 use std::collections::HashSet;
+use std::fs;
+use std::path::Path;
 use std::process::Command;
 
-use super::contains;
+use super::{TempFolder, contains};
 
-/// Get a list of installed packages.
-pub fn list_installed() -> HashSet<String> {
-    String::from_utf8(Command::new("pacman").arg("-Qq").output().unwrap().stdout)
-        .unwrap()
-        .lines()
-        .map(String::from)
-        .collect()
-}
+/// The fake package manager program.
+///
+/// # Operations
+/// - `list` - Print the installed packages, one per line.
+/// - `install [packages]` - Install `packages`, or the packages on stdin if
+///   none are given. Installing an installed package does nothing.
+/// - `remove [packages]` - Uninstall `packages`.
+const SCRIPT: &str = r#"#!/bin/sh
+folder="$(dirname "$0")"
+state="$folder/installed.txt"
+echo "$*" >> "$folder/invocations.log"
+operation="$1"
+shift
+case "$operation" in
+    list)
+        cat "$state"
+        ;;
+    install)
+        if [ $# -eq 0 ]; then
+            set -- $(cat)
+        fi
+        for package in "$@"; do
+            grep -qxF -- "$package" "$state" || echo "$package" >> "$state"
+        done
+        ;;
+    remove)
+        for package in "$@"; do
+            grep -vxF -- "$package" "$state" > "$state.tmp"
+            mv "$state.tmp" "$state"
+        done
+        ;;
+    *)
+        echo "unknown operation: $operation" >&2
+        exit 2
+        ;;
+esac
+"#;
+const SCRIPT_FILENAME: &str = "fake-package-manager";
+const STATE_FILENAME: &str = "installed.txt";
+const INVOCATIONS_FILENAME: &str = "invocations.log";
 
-/// Checks that all packages in `packages` are installed.
-pub fn check_installed(packages: &[&str]) -> bool {
-    let installed_packages = list_installed();
-    contains::contains_all(&installed_packages.iter().map(String::as_str).collect::<Vec<_>>(), packages)
+/// A fake package manager in a temporary folder that is removed when dropped.
+pub struct FakePackageManager {
+    folder: TempFolder,
 }
+impl FakePackageManager {
+    /// Create a fake package manager with nothing installed.
+    pub fn new() -> Self {
+        let folder = TempFolder::new("fake-package-manager");
+        fs::write(folder.path().join(SCRIPT_FILENAME), SCRIPT).unwrap();
+        fs::write(folder.path().join(STATE_FILENAME), "").unwrap();
+        fs::write(folder.path().join(INVOCATIONS_FILENAME), "").unwrap();
+        Self { folder }
+    }
 
-/// Check that all packages in `packages` are *not* installed.
-pub fn check_not_installed(packages: &[&str]) -> bool {
-    let installed_packages = list_installed();
-    contains::contains_none(&installed_packages.iter().map(String::as_str).collect::<Vec<_>>(), packages)
-}
+    /// Create a fake package manager with `packages` installed.
+    pub fn with_installed(packages: &[&str]) -> Self {
+        let fake = Self::new();
+        fake.install_packages(packages);
+        fake
+    }
 
-/// Install `packages`.
-pub fn install_packages(packages: &[&str]) {
-    Command::new("sudo")
-        .args(["pacman", "-S", "--noconfirm"])
-        .args(packages)
-        .status()
-        .unwrap();
-    assert!(check_installed(packages));
-}
+    /// Get the fake's temporary folder. Anything created in it is removed
+    /// with the fake.
+    pub fn folder(&self) -> &Path {
+        self.folder.path()
+    }
+
+    /// Build a command that runs the fake's `operation`. The script is run
+    /// through `sh` so it never has to be executable.
+    pub fn command(&self, operation: &str) -> Command {
+        let mut command = Command::new("sh");
+        command.arg(self.folder.path().join(SCRIPT_FILENAME)).arg(operation);
+        command
+    }
+
+    /// Get the command tables of a package manager file that uses the fake.
+    pub fn package_manager_toml(&self) -> String {
+        let script = self.folder.path().join(SCRIPT_FILENAME);
+        format!(
+            "[install_command]\ncommand = \"sh\"\nargs = [\"{0}\", \"install\"]\n\n[list_command]\ncommand = \"sh\"\nargs = [\"{0}\", \"list\"]\n",
+            script.to_string_lossy()
+        )
+    }
+
+    /// Get the installed packages.
+    pub fn list_installed(&self) -> HashSet<String> {
+        fs::read_to_string(self.folder.path().join(STATE_FILENAME))
+            .unwrap()
+            .lines()
+            .map(String::from)
+            .collect()
+    }
 
-/// Uninstalled `packages`.
-pub fn remove_packages(packages: &[&str]) {
-    Command::new("sudo")
-        .args(["pacman", "-Rs", "--noconfirm"])
-        .args(packages)
-        .status()
-        .unwrap();
-    assert!(check_not_installed(packages));
+    /// Checks that all packages in `packages` are installed.
+    pub fn check_installed(&self, packages: &[&str]) -> bool {
+        let installed_packages = self.list_installed();
+        contains::contains_all(&installed_packages.iter().map(String::as_str).collect::<Vec<_>>(), packages)
+    }
+
+    /// Check that all packages in `packages` are *not* installed.
+    pub fn check_not_installed(&self, packages: &[&str]) -> bool {
+        let installed_packages = self.list_installed();
+        contains::contains_none(&installed_packages.iter().map(String::as_str).collect::<Vec<_>>(), packages)
+    }
+
+    /// Install `packages` without recording an invocation.
+    pub fn install_packages(&self, packages: &[&str]) {
+        self.run_unrecorded("install", packages);
+        assert!(self.check_installed(packages));
+    }
+
+    /// Uninstall `packages` without recording an invocation.
+    pub fn remove_packages(&self, packages: &[&str]) {
+        self.run_unrecorded("remove", packages);
+        assert!(self.check_not_installed(packages));
+    }
+
+    /// Get the arguments of every invocation so far, separated by spaces.
+    pub fn invocations(&self) -> Vec<String> {
+        fs::read_to_string(self.folder.path().join(INVOCATIONS_FILENAME))
+            .unwrap()
+            .lines()
+            .map(String::from)
+            .collect()
+    }
+
+    /// Helper function to run `operation` on `packages` without it showing up
+    /// in `invocations()`.
+    fn run_unrecorded(&self, operation: &str, packages: &[&str]) {
+        let invocations_file = self.folder.path().join(INVOCATIONS_FILENAME);
+        let invocations = fs::read_to_string(&invocations_file).unwrap();
+        assert!(self.command(operation).args(packages).status().unwrap().success());
+        fs::write(invocations_file, invocations).unwrap();
+    }
+}
+impl Default for FakePackageManager {
+    fn default() -> Self {
+        Self::new()
+    }
 }
@@ -1,6 +1,11 @@
 //! Utilities for testing.
 use std::fs;
 use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+
+/// Counts the temporary folders created by this process, to give each its own
+/// parent folder.
+static TEMP_FOLDER_COUNT: AtomicUsize = AtomicUsize::new(0);
 
 /// Automatically removes the specified path when dropped.
 pub struct PathRemover {
@@ -23,3 +28,30 @@ impl Drop for PathRemover {
         .unwrap()
     }
 }
+
+/// An empty folder in the system's temporary folder that is removed, along with
+/// everything in it, when dropped.
+pub struct TempFolder {
+    path: PathBuf,
+    _path_remover: PathRemover,
+}
+impl TempFolder {
+    /// Create a temporary folder named `name`. It is put in a parent folder of
+    /// its own, so tests can use any name and still run in parallel.
+    pub fn new(name: &str) -> Self {
+        let parent = std::env::temp_dir().join(format!("dotfile-test-{}-{}", std::process::id(), TEMP_FOLDER_COUNT.fetch_add(1, Ordering::Relaxed)));
+        // a folder left over from a crashed run with the same process ID
+        let _ = fs::remove_dir_all(&parent);
+        let path = parent.join(name);
+        fs::create_dir_all(&path).unwrap();
+        Self {
+            path,
+            _path_remover: PathRemover::new(parent),
+        }
+    }
+
+    /// Get the folder's path.
+    pub fn path(&self) -> &Path {
+        &self.path
+    }
+}
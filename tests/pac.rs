@@ -2,12 +2,11 @@
 
 use std::fs;
 use std::path::PathBuf;
-use std::process::Command;
 use std::sync::LazyLock;
 
 use dotfile::pac::*;
 use rstest::*;
-use test_utils::{self, PathRemover};
+use test_utils::{self, FakePackageManager, TempFolder};
 
 static PAC_TEST_FILES_FOLDER: LazyLock<PathBuf> = LazyLock::new(|| PathBuf::from("test_files/pac"));
 
@@ -26,42 +25,27 @@ mod package_manager_tests {
 
         /// Test the installation of a single package.
         #[rstest]
-        #[ignore = "must be run single-threaded"]
-        fn single(package_manager: PackageManager) {
+        fn single(package_manager: (FakePackageManager, PackageManager)) {
             test_install(package_manager, &[TEST_PACKAGE1]);
         }
 
         /// Test the installation of multiple packages.
         #[rstest]
-        #[ignore = "must be run single-threaded"]
-        fn multiple(package_manager: PackageManager) {
+        fn multiple(package_manager: (FakePackageManager, PackageManager)) {
             test_install(package_manager, &[TEST_PACKAGE1, TEST_PACKAGE2]);
         }
 
         /// Helper function for testing `install()`.
-        fn test_install(mut package_manager: PackageManager, packages: &[&str]) {
-            let _package_handler = PackageAutoHandler::new(packages); // packages uninstalled here
-            package_manager.install(packages).unwrap();
-            assert!(test_utils::check_installed(packages));
-        } // packages reinstalled here
-
-        /// Automatically removes the provided `packages` on construction and
-        /// reinstalls them when dropped.
-        struct PackageAutoHandler<'a> {
-            packages: &'a [&'a str],
-        }
-        impl<'a> PackageAutoHandler<'a> {
-            /// Create a new instance. Uninstalls all `packages`.
-            pub fn new(packages: &'a [&'a str]) -> Self {
-                test_utils::remove_packages(packages);
-                Self { packages }
-            }
-        }
-        impl<'a> Drop for PackageAutoHandler<'a> {
-            /// Reinstall the `packages`.
-            fn drop(&mut self) {
-                test_utils::install_packages(self.packages);
-            }
+        fn test_install((fake, mut package_manager): (FakePackageManager, PackageManager), packages: &[&str]) {
+            assert!(fake.check_not_installed(packages));
+            let report = package_manager.install(packages).unwrap();
+            assert!(report.is_success());
+            assert!(fake.check_installed(packages));
+            assert_eq!(fake.invocations(), [String::from("list"), format!("install {}", packages.join(" "))]);
+            // installed packages are skipped
+            let report = package_manager.install(packages).unwrap();
+            assert!(report.installed.is_empty());
+            assert_eq!(report.already_installed.len(), packages.len());
         }
     }
 
@@ -72,19 +56,22 @@ mod package_manager_tests {
 
         /// Make sure the list command does what it should.
         #[rstest]
-        fn it_works(mut package_manager: PackageManager) {
-            assert_eq!(package_manager.list().unwrap(), test_utils::list_installed());
+        fn it_works(#[from(package_manager)] (fake, mut package_manager): (FakePackageManager, PackageManager)) {
+            assert!(package_manager.list().unwrap().is_empty());
+            fake.install_packages(&[TEST_PACKAGE1, TEST_PACKAGE2]);
+            assert_eq!(package_manager.list().unwrap(), fake.list_installed());
+            fake.remove_packages(&[TEST_PACKAGE1]);
+            assert_eq!(package_manager.list().unwrap(), fake.list_installed());
+            assert_eq!(fake.invocations(), ["list", "list", "list"]);
         }
     }
 
-    /// Creates a valid package manager.
+    /// Creates a valid package manager backed by a fake with nothing installed.
     #[fixture]
-    fn package_manager() -> PackageManager {
-        let mut install_command = Command::new("sudo");
-        install_command.args(["pacman", "-S", "--noconfirm"]);
-        let mut list_command = Command::new("pacman");
-        list_command.arg("-Qq");
-        PackageManager::build(install_command, list_command)
+    fn package_manager() -> (FakePackageManager, PackageManager) {
+        let fake = FakePackageManager::new();
+        let package_manager = PackageManager::build(fake.command("install"), fake.command("list"));
+        (fake, package_manager)
     }
 }
 
@@ -95,68 +82,84 @@ mod package_system_tests {
 
     static TEST_FILES_FOLDER: LazyLock<PathBuf> = LazyLock::new(|| PAC_TEST_FILES_FOLDER.join("package_system"));
 
-    /// Tests for functions that read files.
-    #[cfg(test)]
-    mod file_read_tests {
-        use super::*;
-
-        static TEST_FILES_FOLDER: LazyLock<PathBuf> = LazyLock::new(|| TEST_FILES_FOLDER.join("read"));
-        static EXISTS_FOLDER: LazyLock<PathBuf> = LazyLock::new(|| TEST_FILES_FOLDER.join("exists"));
-        static DOES_NOT_EXIST_FOLDER: LazyLock<PathBuf> = LazyLock::new(|| TEST_FILES_FOLDER.join("does-not-exist"));
-
-        /// Tests `PackageSystem.read_packages_file()`.
-        #[rstest]
-        #[ignore = "must be run single-threaded"]
-        fn read_packages_file(#[from(package_systems)] (exists_package_system, does_not_exist_package_system): (PackageSystem, PackageSystem)) {
-            // test the case where the file exists
-            let packages_file = EXISTS_FOLDER.join(PACKAGES_FILENAME);
+    /// Copy the test files folder named `name` into `fake`'s folder and add a
+    /// package manager file that uses `fake`. Returns the new folder.
+    fn package_system_folder(fake: &FakePackageManager, name: &str) -> PathBuf {
+        let folder = fake.folder().join(name);
+        fs::create_dir_all(&folder).unwrap();
+        for entry in fs::read_dir(TEST_FILES_FOLDER.join(name)).unwrap() {
+            let path = entry.unwrap().path();
+            fs::copy(&path, folder.join(path.file_name().unwrap())).unwrap();
         }
+        fs::write(folder.join(PACKAGE_MANAGER_FILENAME), fake.package_manager_toml()).unwrap();
+        folder
+    }
 
-        /// Tests `PackageSystem.read_excluded_packages_file()`.
-        #[rstest]
-        #[ignore = "must be run single-threaded"]
-        fn read_excluded_packages_file() {}
-
-        // Creates two package systems: one for the `exists` folder and one for the `does-not-exist` folder.
-        #[fixture]
-        fn package_systems() -> (PackageSystem, PackageSystem) {
-            (PackageSystem::from_folder(EXISTS_FOLDER.as_path()).unwrap(), PackageSystem::from_folder(DOES_NOT_EXIST_FOLDER.as_path()).unwrap())
-        }
+    /// Tests `PackageSystem.install()`.
+    #[test]
+    fn install() {
+        let fake = FakePackageManager::with_installed(&["git"]);
+        let folder = package_system_folder(&fake, "install");
+        let mut package_system = PackageSystem::from_folder(&folder).unwrap();
+        package_system.install().unwrap();
+        // only the missing packages were installed
+        assert!(fake.check_installed(&["git", "vim", "htop"]));
+        assert_eq!(fake.invocations(), ["list", "install htop vim"]);
+        // nothing is missing the second time
+        package_system.install().unwrap();
+        assert_eq!(fake.invocations(), ["list", "install htop vim", "list"]);
     }
 
     /// Tests `PackageSystem.upload()`.
     #[test]
-    #[ignore = "must be run single-threaded"]
     fn upload() {
         let excluded_packages = ["nano", "trash-cli"];
-        // make sure the test excluded packages are installed; can't test otherwise
-        assert!(test_utils::check_installed(&excluded_packages), "Test excluded packages not installed: {:?}", &excluded_packages);
-        // setup
-        let folder = TEST_FILES_FOLDER.join("upload");
-        let installed_packages_file = folder.join(PACKAGES_FILENAME);
-        let _file_remover = PathRemover::new(&installed_packages_file);
-        let mut package_system = PackageSystem::from_folder(folder).unwrap();
+        let fake = FakePackageManager::with_installed(&["git", "nano", "trash-cli", "vim"]);
+        let folder = package_system_folder(&fake, "upload");
+        let mut package_system = PackageSystem::from_folder(&folder).unwrap();
         // upload
         package_system.upload().unwrap();
-        // get the packages the package system uploaded
-        let uploaded_packages = utils::read_file_to_hashset(&installed_packages_file).unwrap();
+        // get the packages the package system uploaded; new packages go to the host layer
+        let uploaded_packages = utils::read_file_to_hashset(&package_system.layer_file(package_system.host_layer())).unwrap();
         // check that all uploaded packages are actually installed
-        assert!(test_utils::contains_all(&test_utils::list_installed().iter().collect::<Vec<_>>(), &uploaded_packages.iter().collect::<Vec<_>>()));
+        assert!(test_utils::contains_all(&fake.list_installed().iter().collect::<Vec<_>>(), &uploaded_packages.iter().collect::<Vec<_>>()));
         // check that the excluded packages were not uploaded
         assert!(test_utils::contains_none(&uploaded_packages.iter().map(String::as_str).collect::<Vec<_>>(), &excluded_packages));
+        assert_eq!(uploaded_packages.len(), 2);
+        assert_eq!(fake.invocations(), ["list"]);
+    }
+
+    /// Tests `PackageSystem.status()`.
+    #[test]
+    fn status() {
+        let fake = FakePackageManager::with_installed(&["git", "nano"]);
+        let folder = package_system_folder(&fake, "status");
+        let status = PackageSystem::from_folder(&folder).unwrap().status().unwrap();
+        assert_eq!(status.not_installed.iter().collect::<Vec<_>>(), ["vim"]);
+        assert_eq!(status.not_recorded.iter().collect::<Vec<_>>(), ["git"]);
+        assert_eq!(status.excluded_installed.iter().collect::<Vec<_>>(), ["nano"]);
+    }
+
+    /// Package manager files can't pick the `memory` backend, which only
+    /// exists for tests.
+    #[test]
+    fn memory_backend() {
+        let temp_folder = TempFolder::new("memory");
+        let folder = temp_folder.path();
+        fs::write(folder.join(PACKAGE_MANAGER_FILENAME), "type = \"memory\"\ninstalled = [\"git\"]\n").unwrap();
+        let error = PackageSystem::from_folder(folder).err().unwrap();
+        assert!(error.to_string().contains("memory"), "{}", error);
     }
 
     /// Tests `new_package_system()`.
     #[test]
-    #[ignore = "must be run single-threaded"]
     fn test_new_package_system() {
         // keep track of the folder where the package system gets created
-        let temp_folder = TEST_FILES_FOLDER.join("TEMP");
-        let _path_remover = PathRemover::new(&temp_folder);
+        let temp_folder = TempFolder::new("new_package_system");
         // create a new package manager
         let name = "test_package_system";
-        let package_system_folder = temp_folder.join(name);
-        new_package_system(&temp_folder, name).unwrap(); // run the command
+        let package_system_folder = temp_folder.path().join(name);
+        new_package_system(temp_folder.path(), name, None).unwrap(); // run the command
         // make sure the package manager contents are correct
         let correct_package_manager_contents = include_str!("../templates/package-manager.toml");
         assert_eq!(
@@ -167,6 +170,67 @@ mod package_system_tests {
         assert!(&package_system_folder.join(package_system::PACKAGES_FILENAME).exists());
         assert!(&package_system_folder.join(package_system::EXCLUDED_PACKAGES_FILENAME).exists());
     }
+
+    /// Tests `new_package_system()` with a preset.
+    #[test]
+    fn test_new_package_system_preset() {
+        let temp_folder = TempFolder::new("new_package_system_preset");
+        let folder = temp_folder.path();
+        new_package_system(folder, "apt", Some("apt")).unwrap();
+        // the package manager file should be the preset
+        let package_system = PackageSystem::from_folder(folder.join("apt")).unwrap();
+        assert_eq!(package_system.package_manager().programs(), ["apt-get", "apt-mark"]);
+        assert!(package_system.package_manager().needs_root());
+        // unknown presets create nothing
+        assert!(new_package_system(folder, "unknown", Some("unknown")).is_err());
+        assert!(!folder.join("unknown").exists());
+    }
+}
+
+/// Tests the `all` module.
+#[cfg(test)]
+mod all_tests {
+    use super::*;
+
+    static TEST_FILES_FOLDER: LazyLock<PathBuf> = LazyLock::new(|| PAC_TEST_FILES_FOLDER.join("all"));
+
+    /// Tests `dependency_order()` with a valid dependency.
+    #[test]
+    fn ordered() {
+        let package_systems = package_systems_from_folder(PAC_TEST_FILES_FOLDER.join("parser/test_package_systems")).unwrap();
+        assert_eq!(dependency_order(&package_systems).unwrap(), ["pacman", "yay"]);
+    }
+
+    /// Tests `dependency_order()` with a dependency cycle.
+    #[test]
+    fn cycle() {
+        let package_systems = package_systems_from_folder(TEST_FILES_FOLDER.join("cycle")).unwrap();
+        let error = dependency_order(&package_systems).unwrap_err().to_string();
+        assert!(error.contains("cycle") && error.contains("a, b"), "{}", error);
+    }
+
+    /// Tests `dependency_order()` with a missing dependency.
+    #[test]
+    fn missing() {
+        let package_systems = package_systems_from_folder(TEST_FILES_FOLDER.join("missing")).unwrap();
+        let error = dependency_order(&package_systems).unwrap_err().to_string();
+        assert!(error.contains("does-not-exist"), "{}", error);
+    }
+}
+
+/// Tests the `check` module.
+#[cfg(test)]
+mod check_tests {
+    use super::*;
+
+    /// Tests `check_folder()`.
+    #[test]
+    fn check_package_systems() {
+        let reports = check_folder(PAC_TEST_FILES_FOLDER.join("check"), None).unwrap();
+        assert_eq!(reports.iter().map(|report| report.name.as_str()).collect::<Vec<_>>(), ["good", "typo"]);
+        assert!(reports[0].is_ok(), "{}", reports[0]);
+        assert_eq!(reports[1].problems, ["unknown key `check_comand`"]);
+    }
 }
 
 /// Tests the `parser` module.
@@ -184,21 +248,81 @@ mod parser_tests {
         // verify pacman is correct
         let pacman = &package_managers.get("pacman").unwrap();
         assert_eq!(pacman.name(), "pacman");
-        let pacman_manager = pacman.package_manager();
-        assert_eq!(pacman_manager.install_command.get_program().to_str().unwrap(), "sudo");
-        assert_eq!(pacman_manager.install_command.get_args().collect::<Vec<_>>(), vec!["pacman", "-S", "--needed", "--noconfirm"]);
-        assert_eq!(pacman_manager.list_command.get_program().to_str().unwrap(), "pacman");
-        assert_eq!(pacman_manager.list_command.get_args().collect::<Vec<_>>(), vec!["-Qqen"]);
+        assert_eq!(pacman.package_manager().programs(), ["sudo", "pacman", "pacman"]);
+        assert!(pacman.commands().contains(r#"args = ["pacman", "-S", "--needed", "--noconfirm"]"#), "{}", pacman.commands());
+        assert!(pacman.commands().contains(r#"args = ["-Qqen"]"#), "{}", pacman.commands());
 
         // verify yay is correct
         let yay = &package_managers.get("yay").unwrap();
         assert_eq!(yay.name(), "yay");
-        let yay_manager = yay.package_manager();
-        assert_eq!(yay_manager.install_command.get_program().to_str().unwrap(), "yay");
-        assert_eq!(yay_manager.install_command.get_args().collect::<Vec<_>>(), vec!["-S", "--needed", "--noconfirm"]);
-        assert_eq!(yay_manager.list_command.get_program().to_str().unwrap(), "pacman");
-        assert_eq!(yay_manager.list_command.get_args().collect::<Vec<_>>(), vec!["-Qqem"]);
+        assert_eq!(yay.package_manager().programs(), ["yay", "pacman"]);
+        assert!(yay.commands().contains(r#"args = ["-S", "--needed", "--noconfirm"]"#), "{}", yay.commands());
+        assert!(yay.commands().contains(r#"args = ["-Qqem"]"#), "{}", yay.commands());
 
         assert!(yay == yay);
     }
+
+    /// Tests `package_systems_from_folder()` with a templated package manager file.
+    #[test]
+    fn templated_package_systems() {
+        let package_systems = package_systems_from_folder(PAC_TEST_FILES_FOLDER.join("parser/templated_package_systems")).unwrap();
+        let package_system = package_systems.get("templated").unwrap();
+        assert_eq!(package_system.package_manager().programs(), ["paru", "pacman"]);
+        let list_args = format!(r#"args = ["-Qqem", "--arch={}"]"#, std::env::consts::ARCH);
+        assert!(package_system.commands().contains(&list_args), "{}", package_system.commands());
+    }
+
+    /// Tests the global `privilege` setting and a package system overriding it.
+    #[test]
+    fn privilege_settings() {
+        let package_systems = package_systems_from_folder(PAC_TEST_FILES_FOLDER.join("parser/privilege_package_systems")).unwrap();
+        let privilege = |name: &str| package_systems[name].package_manager().privilege();
+        assert_eq!(privilege("global"), Privilege::Doas);
+        assert_eq!(privilege("override"), Privilege::None);
+    }
+
+    /// Tests the global `hosts` setting picking this host's profiles.
+    #[test]
+    fn host_profiles() {
+        let temp_folder = TempFolder::new("host_profiles");
+        let folder = temp_folder.path();
+        fs::create_dir_all(folder.join("system")).unwrap();
+        fs::copy(
+            PAC_TEST_FILES_FOLDER
+                .join("parser/privilege_package_systems/global")
+                .join(PACKAGE_MANAGER_FILENAME),
+            folder.join("system").join(PACKAGE_MANAGER_FILENAME),
+        )
+        .unwrap();
+        // the host layer is named after whatever machine runs the test
+        let hostname = TemplateContext::detect().hostname;
+        fs::write(folder.join(SETTINGS_FILENAME), format!("[hosts]\n\"{}\" = [\"laptop\"]\nother = [\"gaming\"]\n", hostname)).unwrap();
+        let package_systems = package_systems_from_folder(folder).unwrap();
+        let package_system = package_systems.get("system").unwrap();
+        assert_eq!(package_system.layers(), [COMMON_LAYER, "laptop", package_system.host_layer()]);
+    }
+
+    /// Tests `default_package_system_name()` with a package system marked as the default.
+    #[test]
+    fn marked_default_package_system() {
+        let package_systems = package_systems_from_folder(PAC_TEST_FILES_FOLDER.join("parser/default_package_systems")).unwrap();
+        assert_eq!(default_package_system_name(&package_systems).unwrap(), "marked");
+    }
+
+    /// Tests `default_package_system_name()` detecting the available package system.
+    #[test]
+    fn detected_default_package_system() {
+        let package_systems = package_systems_from_folder(PAC_TEST_FILES_FOLDER.join("parser/detect_package_systems")).unwrap();
+        assert_eq!(default_package_system_name(&package_systems).unwrap(), "available");
+    }
+
+    /// Tests `default_package_system_name()` when the choice is ambiguous.
+    #[test]
+    fn ambiguous_default_package_system() {
+        let package_systems = package_systems_from_folder(PAC_TEST_FILES_FOLDER.join("parser/ambiguous_package_systems")).unwrap();
+        let error = default_package_system_name(&package_systems).unwrap_err().to_string();
+        // both available candidates must be named, and the missing one must not
+        assert!(error.contains("first") && error.contains("second"), "{}", error);
+        assert!(!error.contains("missing"), "{}", error);
+    }
 }
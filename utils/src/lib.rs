@@ -1,2 +1,5 @@
 mod file;
 pub use file::*;
+
+mod program;
+pub use program::*;
@@ -0,0 +1,21 @@
+//! Utilities for external programs.
+use std::env;
+use std::ffi::OsStr;
+use std::path::{Path, PathBuf};
+
+#[cfg(test)]
+mod tests;
+
+/// Find `program` on the `PATH`. If `program` contains a path separator, it is
+/// checked directly instead.
+//# TESTED
+pub fn find_program(program: impl AsRef<OsStr>) -> Option<PathBuf> {
+    let program = Path::new(program.as_ref());
+    if program.as_os_str().is_empty() {
+        return None;
+    }
+    if program.components().count() > 1 {
+        return program.is_file().then(|| program.to_path_buf());
+    }
+    env::split_paths(&env::var_os("PATH")?).map(|dir| dir.join(program)).find(|candidate| candidate.is_file())
+}
@@ -0,0 +1,14 @@
+use super::find_program;
+
+/// Tests `find_program()` with a program that exists.
+#[test]
+fn exists() {
+    assert!(find_program("sh").is_some());
+}
+
+/// Tests `find_program()` with a program that does not exist.
+#[test]
+fn does_not_exist() {
+    assert!(find_program("definitely-not-a-real-program").is_none());
+    assert!(find_program("").is_none());
+}